@@ -0,0 +1,77 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use totp_rs::{Secret, TOTP};
+use zeroize::Zeroizing;
+
+use rauthy_lib::storage::{Service, Storage};
+use rauthy_lib::totp::ServicesTokens;
+
+/// Size of the biggest vaults we know of (a shared ops vault)
+const VAULT_SIZE: usize = 500;
+
+fn setup_storage() -> Storage {
+    let mut storage = Storage::new(Zeroizing::new([0; 32]), Vec::new());
+    for i in 0..VAULT_SIZE {
+        let mut service = Service::default();
+        service.issuer = format!("Issuer {}", i);
+        service.name = format!("user{}@example.com", i);
+        service.id = format!("{}{}", service.issuer, service.name);
+        service.secret = Zeroizing::new(
+            Secret::Raw(rand::random::<[u8; 20]>().to_vec())
+                .to_encoded()
+                .to_string(),
+        );
+        // The services the app stores are validated when they're added
+        service.validate().unwrap();
+        storage.add_service(service);
+    }
+    storage
+}
+
+/// What every request used to cost before the generators were cached: decoding the secret
+/// and building a TOTP checked against the RFC, falling back to an unchecked one
+fn uncached_token(service: &Service) -> (String, u64) {
+    let secret = Secret::Encoded(service.secret.to_string()).to_bytes().unwrap();
+    let totp = TOTP::new(
+        service.algorithm,
+        service.digits,
+        1,
+        service.period,
+        secret.clone(),
+        Some(service.issuer.clone()),
+        service.name.clone(),
+    )
+    .unwrap_or_else(|_| {
+        TOTP::new_unchecked(
+            service.algorithm,
+            service.digits,
+            1,
+            service.period,
+            secret,
+            Some(service.issuer.clone()),
+            service.name.clone(),
+        )
+    });
+    (totp.generate_current().unwrap(), totp.next_step_current().unwrap())
+}
+
+fn tokens_benchmark(c: &mut Criterion) {
+    let storage = setup_storage();
+    let mut group = c.benchmark_group("tokens_500_services");
+
+    group.bench_function("uncached_generators", |b| {
+        b.iter(|| {
+            for service in storage.services().values() {
+                black_box(uncached_token(service));
+            }
+        })
+    });
+
+    group.bench_function("cached_generators", |b| {
+        b.iter(|| black_box(storage.services_tokens()))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, tokens_benchmark);
+criterion_main!(benches);
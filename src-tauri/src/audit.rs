@@ -0,0 +1,320 @@
+use data_encoding::BASE64URL_NOPAD;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, KeyArray};
+use crate::error::RauthyError;
+use crate::lockout::unix_now;
+use crate::team;
+
+const AUDIT_EXTENSION: &str = "audit";
+
+/// An operation recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    Unlock,
+    Add,
+    Update,
+    Delete,
+    Import,
+    Export,
+    PasswordChange,
+    RevealSecret,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    /// Unix timestamp (in seconds)
+    pub timestamp: u64,
+    pub action: AuditAction,
+    /// The service (or how many services) the operation is about
+    pub target: Option<String>,
+    pub device: String,
+    /// The member that opened a team vault
+    pub member: Option<String>,
+    /// The code of the error, when the operation failed
+    pub error: Option<String>,
+}
+
+/// What the frontend gets: the events, oldest first, and whether the chain of hashes is intact
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditTrail {
+    pub events: Vec<AuditEvent>,
+    pub intact: bool,
+}
+
+/// First line of the log: the key records are encrypted to, with its secret key wrapped by
+/// the data key of the vault
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Header {
+    public_key: String,
+    wrapped_secret: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    /// SHA-256 of the previous line (of the key of the log, for the first record), so lines
+    /// can't be removed, reordered or edited
+    previous: String,
+    ephemeral_key: String,
+    event: String,
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction, target: Option<String>, member: Option<String>, error: Option<&RauthyError>) -> Self {
+        Self {
+            timestamp: unix_now(),
+            action,
+            target,
+            device: device_name(),
+            member,
+            error: error.map(|err| err.code().to_string()),
+        }
+    }
+}
+
+/// The audit log of a vault, kept next to it (e.g. `Rauthy.audit`), one JSON line per event.
+///
+/// Each event is encrypted to the public key in the first line, so events are recorded even
+/// while the vault is locked (e.g. failed unlock attempts), but they can only be read with
+/// the data key of the vault. Lines are chained by their hashes: editing or removing one
+/// breaks the chain. Anyone that can write the file can still delete its last lines, or the
+/// whole file, like the failed attempts counter.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn of(vault_path: &Path) -> Self {
+        Self {
+            path: Self::path(vault_path),
+        }
+    }
+
+    /// The log file of the vault at `vault_path` (e.g. `Rauthy.audit`)
+    pub fn path(vault_path: &Path) -> PathBuf {
+        vault_path.with_extension(AUDIT_EXTENSION)
+    }
+
+    /// Creates the log, unless it exists and its key is wrapped by `data_key`
+    pub fn init(&self, data_key: &KeyArray) -> Result<(), RauthyError> {
+        if self.path.exists() {
+            return self.secret(data_key).map(|_| ());
+        }
+        let mut content = header_line(&team::generate_secret(), data_key)?;
+        content.push('\n');
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+
+    /// Appends `event` to the end of the log. Vaults unlocked by older versions have no log
+    /// yet, so their events are dropped.
+    pub fn append(&self, event: &AuditEvent) -> Result<(), RauthyError> {
+        let Ok(content) = fs::read_to_string(&self.path) else {
+            return Ok(());
+        };
+        let header = read_header(&content)?;
+        let public_key = parse_key(&header.public_key)?;
+        // The first event is chained to the key of the log, which doesn't change
+        let previous = content.lines().skip(1).last().unwrap_or(&header.public_key);
+
+        let ephemeral = team::generate_secret();
+        let ephemeral_public_key = PublicKey::from(&ephemeral).to_bytes();
+        let key = record_key(&ephemeral, &public_key, &ephemeral_public_key, &public_key)?;
+        let event = serde_json::to_vec(event).map_err(|err| RauthyError::Io(err.to_string()))?;
+        let record = Record {
+            previous: line_hash(previous),
+            ephemeral_key: BASE64URL_NOPAD.encode(&ephemeral_public_key),
+            event: BASE64URL_NOPAD.encode(&crypto::encrypt_data(&event, key.as_ref())?),
+        };
+
+        let mut line = serde_json::to_string(&record).map_err(|err| RauthyError::Io(err.to_string()))?;
+        line.push('\n');
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Decrypts the events and checks the chain of hashes. Events that can't be decrypted
+    /// are left out, and the trail is marked as broken.
+    pub fn read(&self, data_key: &KeyArray) -> Result<AuditTrail, RauthyError> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(_) => return Ok(AuditTrail { events: Vec::new(), intact: true }),
+        };
+        let secret = self.secret(data_key)?;
+        let public_key = PublicKey::from(&secret).to_bytes();
+
+        let mut trail = AuditTrail { events: Vec::new(), intact: true };
+        let header = read_header(&content)?;
+        let mut previous = header.public_key.as_str();
+        for line in content.lines().skip(1) {
+            let event = serde_json::from_str::<Record>(line).ok().and_then(|record| {
+                let ephemeral_public_key = parse_key(&record.ephemeral_key).ok()?;
+                let key = record_key(&secret, &ephemeral_public_key, &ephemeral_public_key, &public_key).ok()?;
+                let encrypted = BASE64URL_NOPAD.decode(record.event.as_bytes()).ok()?;
+                let event = crypto::decrypt_data(&encrypted, key.as_ref()).ok()?;
+                let event = serde_json::from_slice::<AuditEvent>(&event).ok()?;
+                Some((record.previous, event))
+            });
+            match event {
+                Some((hash, event)) => {
+                    trail.intact &= hash == line_hash(previous);
+                    trail.events.push(event);
+                }
+                None => trail.intact = false,
+            }
+            previous = line;
+        }
+        Ok(trail)
+    }
+
+    /// Wraps the key of the log with the new data key of the vault, after it was re-keyed
+    pub fn rewrap(&self, previous_key: &KeyArray, data_key: &KeyArray) -> Result<(), RauthyError> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let secret = self.secret(previous_key)?;
+        let content = fs::read_to_string(&self.path)?;
+        let records = content.split_once('\n').map_or("", |(_, records)| records);
+        let header = header_line(&secret, data_key)?;
+        fs::write(&self.path, format!("{}\n{}", header, records))?;
+        Ok(())
+    }
+
+    fn secret(&self, data_key: &KeyArray) -> Result<StaticSecret, RauthyError> {
+        let header = read_header(&fs::read_to_string(&self.path)?)?;
+        let wrapped = BASE64URL_NOPAD.decode(header.wrapped_secret.as_bytes()).map_err(|_| tampered())?;
+        let decrypted = crypto::decrypt_data(&wrapped, data_key).map_err(|_| tampered())?;
+        let mut secret = Zeroizing::new([0u8; 32]);
+        if decrypted.len() != secret.len() {
+            return Err(tampered());
+        }
+        secret.copy_from_slice(&decrypted);
+        let secret = StaticSecret::from(*secret);
+        if PublicKey::from(&secret).to_bytes() != parse_key(&header.public_key)? {
+            return Err(tampered());
+        }
+        Ok(secret)
+    }
+}
+
+/// The name of this computer or phone, to tell the devices that share a vault apart
+pub fn device_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| std::env::consts::OS.to_string())
+}
+
+fn read_header(content: &str) -> Result<Header, RauthyError> {
+    content
+        .lines()
+        .next()
+        .and_then(|line| serde_json::from_str(line).ok())
+        .ok_or_else(tampered)
+}
+
+fn header_line(secret: &StaticSecret, data_key: &KeyArray) -> Result<String, RauthyError> {
+    let header = Header {
+        public_key: BASE64URL_NOPAD.encode(PublicKey::from(secret).as_bytes()),
+        wrapped_secret: BASE64URL_NOPAD.encode(&crypto::encrypt_data(secret.as_bytes(), data_key)?),
+    };
+    serde_json::to_string(&header).map_err(|err| RauthyError::Io(err.to_string()))
+}
+
+fn line_hash(line: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, line.as_bytes()))
+}
+
+fn parse_key(text: &str) -> Result<[u8; 32], RauthyError> {
+    BASE64URL_NOPAD
+        .decode(text.as_bytes())
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(tampered)
+}
+
+/// The key of a record, from the agreement between the ephemeral key of the record and the
+/// key of the log, like the member slots of team vaults
+fn record_key(
+    secret: &StaticSecret,
+    peer_public_key: &[u8; 32],
+    ephemeral_public_key: &[u8; 32],
+    log_public_key: &[u8; 32],
+) -> Result<Zeroizing<KeyArray>, RauthyError> {
+    let shared_secret = secret.diffie_hellman(&PublicKey::from(*peer_public_key));
+    if !shared_secret.was_contributory() {
+        return Err(tampered());
+    }
+    let salt = [ephemeral_public_key.as_slice(), log_public_key].concat();
+    crypto::derive_key_from_secret_and_salt(shared_secret.as_bytes(), &salt)
+}
+
+fn tampered() -> RauthyError {
+    RauthyError::AuditLog("The audit log was tampered with")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+    use std::env;
+
+    fn event(action: AuditAction) -> AuditEvent {
+        AuditEvent::new(action, Some(String::from("GitHub")), None, None)
+    }
+
+    #[test]
+    fn test_events_are_chained_and_encrypted() {
+        let dir = env::temp_dir().join(format!("rauthy-audit-{}", rand::rng().next_u32()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = AuditLog::of(&dir.join("Rauthy.bin"));
+        let data_key = crypto::generate_key();
+
+        // Nothing is recorded before the log is created
+        log.append(&event(AuditAction::Unlock)).unwrap();
+        log.init(&data_key).unwrap();
+        log.append(&event(AuditAction::Unlock)).unwrap();
+        log.append(&AuditEvent::new(AuditAction::RevealSecret, None, None, Some(&RauthyError::WrongPassword))).unwrap();
+        log.append(&event(AuditAction::Export)).unwrap();
+
+        let trail = log.read(&data_key).unwrap();
+        assert!(trail.intact);
+        assert_eq!(trail.events.len(), 3);
+        assert_eq!(trail.events[1].error.as_deref(), Some("WRONG_PASSWORD"));
+        assert!(!fs::read_to_string(&log.path).unwrap().contains("GitHub"));
+        assert!(log.read(&crypto::generate_key()).is_err());
+
+        // The key survives a re-key of the vault
+        let new_key = crypto::generate_key();
+        log.rewrap(&data_key, &new_key).unwrap();
+        assert!(log.read(&new_key).unwrap().intact);
+        assert!(log.init(&data_key).is_err());
+
+        // Removing a line breaks the chain
+        let content = fs::read_to_string(&log.path).unwrap();
+        let mut lines = content.lines().collect::<Vec<_>>();
+        lines.remove(2);
+        fs::write(&log.path, lines.join("\n")).unwrap();
+        let trail = log.read(&new_key).unwrap();
+        assert!(!trail.intact);
+        assert_eq!(trail.events.len(), 2);
+
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,297 @@
+use serde::Serialize;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::settings::Settings;
+use crate::state::AppState;
+use crate::storage::Storage;
+use crate::ticker::refresh_tokens;
+
+/// Event emitted when the vault is locked, with the [`LockReason`]
+pub const LOCKED_EVENT: &str = "locked";
+
+/// Longest time the idle timer sleeps before checking the clock again. `SystemTime` keeps
+/// running while the computer is suspended but the thread timeouts don't, so we can't
+/// sleep the whole timeout at once.
+const MAX_IDLE_WAIT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LockReason {
+    Manual,
+    Idle,
+    Sleep,
+    ScreenLock,
+}
+
+#[derive(Clone, Serialize)]
+struct LockedPayload {
+    reason: LockReason,
+}
+
+/// What the idle timer does next
+#[derive(Debug, PartialEq)]
+enum IdleStep {
+    /// Nothing to lock (or the idle lock is disabled), so wait for a message without any timeout
+    Wait,
+    /// Wait for a message at most this long, then check again
+    WaitFor(Duration),
+    Lock,
+}
+
+pub enum IdleMessage {
+    /// The user interacted with the app
+    Activity,
+    /// The vault was unlocked or the settings changed, so the timeout must be recalculated
+    Reset,
+}
+
+/// Handle to the thread that locks the vault after `Settings::auto_lock_minutes` without activity.
+pub struct IdleTimer {
+    sender: Sender<IdleMessage>,
+}
+
+impl IdleTimer {
+    pub fn send(&self, message: IdleMessage) {
+        // The receiver only goes away when the app is exiting
+        self.sender.send(message).ok();
+    }
+}
+
+/// Starts the idle timer thread. `AppState` must already be managed by the app.
+pub fn spawn_idle_timer<R: Runtime>(app: AppHandle<R>) -> IdleTimer {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || run_idle_timer(app, receiver));
+    IdleTimer { sender }
+}
+
+pub fn record_activity<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(timer) = app.try_state::<IdleTimer>() {
+        timer.send(IdleMessage::Activity);
+    }
+}
+
+pub fn reset_idle_timer<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(timer) = app.try_state::<IdleTimer>() {
+        timer.send(IdleMessage::Reset);
+    }
+}
+
+/// Wipes the key and the services from memory and tells the frontend the vault is locked.
+pub fn lock_vault<R: Runtime>(app: &AppHandle<R>, reason: LockReason) {
+    let app_state = app.state::<Mutex<AppState>>();
+    let mut state = app_state.lock().unwrap();
+    if !lock_storage(&mut state.storage, reason) {
+        return;
+    }
+    drop(state);
+
+    refresh_tokens(app);
+    if let Err(err) = app.emit(LOCKED_EVENT, LockedPayload { reason }) {
+        eprintln!("Couldn't emit the locked event: {err}");
+    }
+}
+
+/// Locks the storage, returning `false` if there was nothing to lock. Manual locks always
+/// go through, so the frontend gets the event even if the vault was already locked.
+fn lock_storage(storage: &mut Storage, reason: LockReason) -> bool {
+    if !storage.is_unlocked() && reason != LockReason::Manual {
+        return false;
+    }
+    storage.lock();
+    true
+}
+
+fn run_idle_timer<R: Runtime>(app: AppHandle<R>, receiver: Receiver<IdleMessage>) {
+    let mut last_activity = SystemTime::now();
+
+    loop {
+        let message = match next_idle_step(current_idle_timeout(&app), last_activity, SystemTime::now()) {
+            IdleStep::Lock => {
+                lock_vault(&app, LockReason::Idle);
+                continue;
+            }
+            IdleStep::WaitFor(wait) => match receiver.recv_timeout(wait) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return,
+            },
+            IdleStep::Wait => match receiver.recv() {
+                Ok(message) => message,
+                Err(_) => return,
+            },
+        };
+
+        match message {
+            IdleMessage::Activity | IdleMessage::Reset => last_activity = SystemTime::now(),
+        }
+    }
+}
+
+fn current_idle_timeout<R: Runtime>(app: &AppHandle<R>) -> Option<Duration> {
+    let app_state = app.state::<Mutex<AppState>>();
+    let state = app_state.lock().unwrap();
+    idle_timeout(state.storage.is_unlocked(), state.settings.auto_lock_minutes)
+}
+
+/// The idle timeout, or `None` if the vault is locked or the idle lock is disabled
+fn idle_timeout(unlocked: bool, auto_lock_minutes: u64) -> Option<Duration> {
+    if !unlocked || auto_lock_minutes == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(auto_lock_minutes * 60))
+}
+
+/// Decides if the vault must be locked at `now`, given the last activity, or how long to
+/// wait before checking again
+fn next_idle_step(timeout: Option<Duration>, last_activity: SystemTime, now: SystemTime) -> IdleStep {
+    let Some(timeout) = timeout else {
+        return IdleStep::Wait;
+    };
+    // The clock may go back, e.g. when it's synchronized, which counts as no idle time
+    let idle = now.duration_since(last_activity).unwrap_or_default();
+    if idle >= timeout {
+        return IdleStep::Lock;
+    }
+    IdleStep::WaitFor((timeout - idle).min(MAX_IDLE_WAIT))
+}
+
+fn setting_enabled<R: Runtime>(app: &AppHandle<R>, enabled: impl Fn(&Settings) -> bool) -> bool {
+    let app_state = app.state::<Mutex<AppState>>();
+    let state = app_state.lock().unwrap();
+    enabled(&state.settings)
+}
+
+/// Listens to the logind `PrepareForSleep` and `Lock` signals to lock the vault when the
+/// computer is suspended or the session is locked. The settings are checked when each
+/// signal arrives, so changing them doesn't require a restart.
+#[cfg(target_os = "linux")]
+pub fn spawn_logind_listener<R: Runtime>(app: AppHandle<R>) {
+    thread::spawn(move || {
+        if let Err(err) = listen_logind_signals(app) {
+            eprintln!("Couldn't listen to the logind signals. Sleep and screen lock won't lock the vault: {err}");
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+fn listen_logind_signals<R: Runtime>(app: AppHandle<R>) -> Result<(), dbus::Error> {
+    use dbus::blocking::Connection;
+    use dbus::message::MatchRule;
+
+    let connection = Connection::new_system()?;
+
+    // Sent with `true` before suspending and `false` after resuming. We lock on both, in case
+    // the computer went to sleep before we had the chance to lock it.
+    let sleep_app = app.clone();
+    let sleep_rule = MatchRule::new_signal("org.freedesktop.login1.Manager", "PrepareForSleep");
+    connection.add_match(sleep_rule, move |(_start,): (bool,), _, _| {
+        if setting_enabled(&sleep_app, |settings| settings.lock_on_sleep) {
+            lock_vault(&sleep_app, LockReason::Sleep);
+        }
+        true
+    })?;
+
+    // Lock is emitted by the session object. If we can't find out our session (e.g. the app
+    // was started by a systemd user service), we listen to the Lock of any session.
+    let mut lock_rule = MatchRule::new_signal("org.freedesktop.login1.Session", "Lock");
+    if let Some(session) = current_session(&connection) {
+        lock_rule = lock_rule.with_path(session);
+    }
+    let lock_app = app.clone();
+    connection.add_match(lock_rule, move |(): (), _, _| {
+        if setting_enabled(&lock_app, |settings| settings.lock_on_screen_lock) {
+            lock_vault(&lock_app, LockReason::ScreenLock);
+        }
+        true
+    })?;
+
+    loop {
+        connection.process(Duration::from_secs(3600))?;
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn current_session(connection: &dbus::blocking::Connection) -> Option<dbus::Path<'static>> {
+    let manager = connection.with_proxy(
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1",
+        Duration::from_secs(5),
+    );
+    let by_pid: Result<(dbus::Path<'static>,), dbus::Error> = manager.method_call(
+        "org.freedesktop.login1.Manager",
+        "GetSessionByPID",
+        (std::process::id(),),
+    );
+    if let Ok((session,)) = by_pid {
+        return Some(session);
+    }
+
+    let session_id = std::env::var("XDG_SESSION_ID").ok()?;
+    let by_id: Result<(dbus::Path<'static>,), dbus::Error> =
+        manager.method_call("org.freedesktop.login1.Manager", "GetSession", (session_id,));
+    by_id.ok().map(|(session,)| session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zeroize::Zeroizing;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_idle_deadline() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let timeout = idle_timeout(true, 5);
+        assert_eq!(timeout, Some(5 * MINUTE));
+
+        // Long timeouts are checked again every MAX_IDLE_WAIT, in case the computer sleeps
+        assert_eq!(next_idle_step(timeout, start, start), IdleStep::WaitFor(MAX_IDLE_WAIT));
+        assert_eq!(
+            next_idle_step(timeout, start, start + 4 * MINUTE + Duration::from_secs(30)),
+            IdleStep::WaitFor(Duration::from_secs(30))
+        );
+        assert_eq!(next_idle_step(timeout, start, start + 5 * MINUTE), IdleStep::Lock);
+        // The clock went back
+        assert_eq!(next_idle_step(timeout, start, start - MINUTE), IdleStep::WaitFor(MAX_IDLE_WAIT));
+    }
+
+    #[test]
+    fn test_activity_resets_idle_timer() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let timeout = idle_timeout(true, 5);
+        let now = start + 6 * MINUTE;
+        assert_eq!(next_idle_step(timeout, start, now), IdleStep::Lock);
+
+        // The activity moves the deadline to 5 minutes after it
+        let last_activity = start + MINUTE + Duration::from_secs(30);
+        assert_eq!(next_idle_step(timeout, last_activity, now), IdleStep::WaitFor(Duration::from_secs(30)));
+        assert_eq!(next_idle_step(timeout, last_activity, now + Duration::from_secs(30)), IdleStep::Lock);
+    }
+
+    #[test]
+    fn test_idle_lock_disabled() {
+        assert_eq!(idle_timeout(true, 0), None);
+        // A locked vault has nothing to lock
+        assert_eq!(idle_timeout(false, 5), None);
+
+        let start = SystemTime::UNIX_EPOCH;
+        let a_year_later = start + Duration::from_secs(365 * 24 * 60 * 60);
+        assert_eq!(next_idle_step(idle_timeout(true, 0), start, a_year_later), IdleStep::Wait);
+    }
+
+    #[test]
+    fn test_lock_wipes_key() {
+        let mut storage = Storage::new(Zeroizing::new([7; 32]), Vec::new());
+        assert!(lock_storage(&mut storage, LockReason::Idle));
+        assert!(!storage.is_unlocked());
+
+        // Nothing to lock, so no event either, except when the user asks for it
+        assert!(!lock_storage(&mut storage, LockReason::Idle));
+        assert!(lock_storage(&mut storage, LockReason::Manual));
+    }
+}
@@ -1,6 +1,8 @@
 use minreq;
 use serde::Deserialize;
 
+use crate::error::RauthyError;
+
 #[derive(Debug, Deserialize)]
 #[allow(non_snake_case, unused)]
 pub struct Brand {
@@ -11,7 +13,7 @@ pub struct Brand {
     pub name: String,
 }
 
-pub fn search_brand(name: &str, client_id: &str) -> Result<Vec<Brand>, Box<dyn std::error::Error>> {
+pub fn search_brand(name: &str, client_id: &str) -> Result<Vec<Brand>, RauthyError> {
     let url = format!(
         "https://api.brandfetch.io/v2/search/{}?c={}",
         name, client_id
@@ -19,14 +21,19 @@ pub fn search_brand(name: &str, client_id: &str) -> Result<Vec<Brand>, Box<dyn s
 
     let response = minreq::get(&url)
         //.add_header("Authorization", &format!("Bearer {}", client_id))
-        .send()?;
+        .send()
+        .map_err(|err| RauthyError::Network(err.to_string()))?;
     if response.status_code == 200 {
         // let body: serde_json::Value = serde_json::from_str(&response.as_str()?)?;
-        let body: Vec<Brand> = serde_json::from_str(&response.as_str()?)?;
+        let body = response
+            .as_str()
+            .map_err(|err| RauthyError::Network(err.to_string()))?;
+        let brands: Vec<Brand> =
+            serde_json::from_str(body).map_err(|err| RauthyError::Network(err.to_string()))?;
         // println!("Brand data: {:?}", body);
-        Ok(body)
+        Ok(brands)
     } else {
         // println!("Error: {}", response.status_code);
-        Err(Box::from(format!("Error: {}", response.status_code)))
+        Err(RauthyError::Network(format!("Error: {}", response.status_code)))
     }
 }
@@ -1,25 +1,52 @@
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Mutex;
 use tauri::State;
-use zeroize::Zeroize;
+use zeroize::Zeroizing;
 use std::env;
 
+use crate::audit::{AuditAction, AuditEvent, AuditTrail};
+use crate::autolock::{lock_vault, record_activity, reset_idle_timer, LockReason};
 use crate::brandfetch::search_brand;
-use crate::crypto::*;
+use crate::crypto::KeyArray;
+use crate::error::RauthyError;
+use crate::history::ServiceVersionView;
+use crate::keyfile::{self, read_key_file};
+use crate::lockout::{device_key, emit_throttled, unix_now, FailedAttempts};
+use crate::organize::{self, ServiceFilter, ServiceGroups, ServiceOrder, SortMode, StaleService, TAG_SEPARATOR};
+use crate::recovery::{generate_recovery_key, parse_recovery_key, parse_share, RecoveryShare};
+use crate::search::SearchMatch;
+use crate::shamir::{combine_shares, split_secret};
+use crate::settings::Settings;
 use crate::state::AppState;
 use crate::storage::*;
+use crate::sync::SyncReport;
+use crate::team::{Identity, IdentityView, TeamMember};
+use crate::ticker::refresh_tokens;
+use crate::transfer::{self, PendingTransfer, TransferOffer};
 use crate::totp::*;
+use crate::vault::{KeySlotKind, VaultFile};
+use crate::vaults::{copy_vault_files, data_dir, delete_vault_files, VaultRegistry};
+use crate::webdav::{Fetched, WebDavClient, WebDavConfig, WebDavStatus};
 
 #[cfg(mobile)]
 use crate::biometric::*;
+#[cfg(mobile)]
+use crate::crypto::generate_key;
+
+/// Marks the biometric secrets, to tell them apart from the passwords stored by older versions
+#[cfg(mobile)]
+const BIOMETRIC_SECRET_PREFIX: &str = "rauthy-biometric:";
 
 #[tauri::command]
 pub fn setup_storage_keys(
     app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
-    user_pass: String,
-) -> Result<ServiceMap, Error> {
-    fetch_services_with_pass(app_handle, app_state, user_pass)
+    user_pass: Zeroizing<String>,
+    key_file: Option<String>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let key_file = key_file.map(|path| read_key_file(Path::new(&path))).transpose()?;
+    fetch_services_with_pass(app_handle, app_state, user_pass, key_file)
 }
 
 #[tauri::command]
@@ -27,20 +54,23 @@ pub fn add_service(
     app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
     totp_uri: &str,
-) -> Result<ServiceMap, ()> {
+) -> Result<ServiceViewMap, RauthyError> {
     let mut state = app_state.lock().unwrap();
-    match Service::try_from(totp_uri) {
-        Ok(service) => {
-            state.storage.add_service(service);
-            state.storage.save_to_file(&app_handle)?;
-
-            let services = state.storage.services().clone();
-            return Ok(services);
-        }
-        Err(_) => {
-            return Ok(std::collections::HashMap::new());
+    let mut service = Service::try_from(totp_uri)?;
+    let target = Some(service.label());
+    let result = service.validate().and_then(|_| {
+        if state.storage.services().contains_key(&service.id) {
+            return Err(RauthyError::Duplicate);
         }
-    }
+        state.storage.add_service(service);
+        state.storage.save_to_file(&app_handle)
+    });
+    state.storage.audit(&app_handle, AuditAction::Add, target, result.as_ref().err());
+    result?;
+    refresh_tokens(&app_handle);
+
+    let services = state.storage.service_views();
+    Ok(services)
 }
 
 #[tauri::command]
@@ -48,13 +78,19 @@ pub fn remove_service(
     app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
     service_id: String,
-) -> Result<ServiceMap, ()> {
+) -> Result<ServiceViewMap, RauthyError> {
     let mut state = app_state.lock().unwrap();
+    let target = state.storage.services().get(&service_id).map(Service::label);
 
-    state.storage.remove_service(service_id);
-    state.storage.save_to_file(&app_handle)?;
+    if !state.storage.remove_service(service_id) {
+        return Err(RauthyError::NotFound);
+    }
+    let result = state.storage.save_to_file(&app_handle);
+    state.storage.audit(&app_handle, AuditAction::Delete, target, result.as_ref().err());
+    result?;
+    refresh_tokens(&app_handle);
 
-    let services = state.storage.services().clone();
+    let services = state.storage.service_views();
 
     Ok(services)
 }
@@ -62,55 +98,320 @@ pub fn remove_service(
 #[tauri::command]
 pub fn get_services_tokens(
     app_state: State<'_, Mutex<AppState>>,
-) -> Result<HashMap<String, TotpToken>, ()> {
+) -> Result<HashMap<String, TokenState>, RauthyError> {
     let state = app_state.lock().unwrap();
-    match state.storage.services_tokens() {
-        Ok(tokens) => Ok(tokens),
-        Err(_) => Err(()),
-    }
+    Ok(state.storage.services_tokens())
 }
 
+/// Updates the fields edited in the frontend. The secret is only replaced when a new one is sent.
 #[tauri::command]
 pub fn update_service(
     app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
-    service: Service,
-) -> Result<(), ()> {
+    service: ServiceView,
+    secret: Option<Zeroizing<String>>,
+) -> Result<(), RauthyError> {
     let mut state = app_state.lock().unwrap();
 
-    state.storage.update_service(service);
-    state.storage.save_to_file(&app_handle)?;
+    let mut updated = state
+        .storage
+        .services()
+        .get(&service.id)
+        .ok_or(RauthyError::NotFound)?
+        .clone();
+    updated.update_from_view(service);
+    if let Some(secret) = secret {
+        updated.secret = secret;
+    }
+
+    let target = Some(updated.label());
+    let result = updated.validate().and_then(|_| {
+        state.storage.update_service(updated);
+        state.storage.save_to_file(&app_handle)
+    });
+    state.storage.audit(&app_handle, AuditAction::Update, target, result.as_ref().err());
+    result?;
+    refresh_tokens(&app_handle);
 
     Ok(())
 }
 
+/// Sends the secret of a service to the frontend. Depending on the settings, the
+/// password must be typed again to authorize it.
+#[tauri::command]
+pub fn reveal_secret(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    service_id: String,
+    password: Option<Zeroizing<String>>,
+) -> Result<Zeroizing<String>, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    if state.settings.require_password_to_reveal {
+        let password = password.ok_or(RauthyError::PasswordRequired)?;
+        let verified = verify_password_throttled(&app_handle, &mut state, &password);
+        if let Err(err) = &verified {
+            let target = state.storage.services().get(&service_id).map(Service::label);
+            state.storage.audit(&app_handle, AuditAction::RevealSecret, target, Some(err));
+        }
+        verified?;
+    }
+
+    let service = state
+        .storage
+        .services()
+        .get(&service_id)
+        .ok_or(RauthyError::NotFound)?
+        .clone();
+    state.storage.audit_first(&app_handle, AuditAction::RevealSecret, Some(service.label()))?;
+    // The secret is revealed even if the use can't be saved
+    state.storage.record_use(&service_id)?;
+    state.storage.save_to_file(&app_handle).ok();
+    Ok(service.secret.clone())
+}
+
 #[tauri::command]
 pub fn delete_service(
     app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
     service_id: String,
-) -> Result<(), String> {
+) -> Result<(), RauthyError> {
     let mut state = app_state.lock().unwrap();
+    let target = state.storage.services().get(&service_id).map(Service::label);
     if state.storage.remove_service(service_id) {
-        state
-            .storage
-            .save_to_file(&app_handle)
-            .map_err(|_| "Failed to save storage".to_string())?;
+        let result = state.storage.save_to_file(&app_handle);
+        state.storage.audit(&app_handle, AuditAction::Delete, target, result.as_ref().err());
+        result?;
+        refresh_tokens(&app_handle);
         Ok(())
     } else {
-        Err("Service not found".to_string())
+        Err(RauthyError::NotFound)
     }
 }
 
+/// The versions of the service replaced by `update_service` or an import, oldest first
+#[tauri::command]
+pub fn get_service_history(
+    app_state: State<'_, Mutex<AppState>>,
+    service_id: String,
+) -> Result<Vec<ServiceVersionView>, RauthyError> {
+    let state = app_state.lock().unwrap();
+    if !state.storage.is_unlocked() {
+        return Err(RauthyError::VaultLocked);
+    }
+    Ok(state.storage.service_history(&service_id))
+}
+
+/// Brings back the version of the service at `index` of `get_service_history`
+#[tauri::command]
+pub fn restore_service_version(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    service_id: String,
+    index: usize,
+) -> Result<ServiceViewMap, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    let target = state.storage.services().get(&service_id).map(Service::label);
+    let result = state
+        .storage
+        .restore_version(&service_id, index)
+        .and_then(|_| state.storage.save_to_file(&app_handle));
+    state.storage.audit(&app_handle, AuditAction::Update, target, result.as_ref().err());
+    result?;
+    refresh_tokens(&app_handle);
+
+    Ok(state.storage.service_views())
+}
+
+/// The services deleted recently, that `undo_delete_service` can bring back
+#[tauri::command]
+pub fn list_deleted_services(app_state: State<'_, Mutex<AppState>>) -> Result<Vec<ServiceVersionView>, RauthyError> {
+    let state = app_state.lock().unwrap();
+    if !state.storage.is_unlocked() {
+        return Err(RauthyError::VaultLocked);
+    }
+    Ok(state.storage.deleted_services())
+}
+
+#[tauri::command]
+pub fn undo_delete_service(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    service_id: String,
+) -> Result<ServiceViewMap, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    let result = state
+        .storage
+        .undo_delete(&service_id)
+        .and_then(|_| state.storage.save_to_file(&app_handle));
+    let target = state.storage.services().get(&service_id).map(Service::label);
+    state.storage.audit(&app_handle, AuditAction::Add, target, result.as_ref().err());
+    result?;
+    refresh_tokens(&app_handle);
+
+    Ok(state.storage.service_views())
+}
+
+/// The services with the tags, in the folder or marked as favorites the filter asks for
+#[tauri::command]
+pub fn filter_services(
+    app_state: State<'_, Mutex<AppState>>,
+    filter: ServiceFilter,
+) -> Result<ServiceViewMap, RauthyError> {
+    let state = app_state.lock().unwrap();
+    Ok(state.storage.filter_services(&filter))
+}
+
+#[tauri::command]
+pub fn list_service_groups(app_state: State<'_, Mutex<AppState>>) -> Result<ServiceGroups, RauthyError> {
+    let state = app_state.lock().unwrap();
+    Ok(state.storage.service_groups())
+}
+
+/// Adds and removes tags of many services at once
+#[tauri::command]
+pub fn tag_services(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    service_ids: Vec<String>,
+    add_tags: Vec<String>,
+    remove_tags: Vec<String>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    let result = state
+        .storage
+        .tag_services(&service_ids, &add_tags, &remove_tags)
+        .and_then(|_| state.storage.save_to_file(&app_handle));
+    let target = Some(format!("Tags of {} services", service_ids.len()));
+    state.storage.audit(&app_handle, AuditAction::Update, target, result.as_ref().err());
+    result?;
+
+    Ok(state.storage.service_views())
+}
+
+/// Moves many services to a folder at once, or out of their folders when `folder` is `None`
+#[tauri::command]
+pub fn move_services_to_folder(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    service_ids: Vec<String>,
+    folder: Option<String>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    let result = state
+        .storage
+        .move_services_to_folder(&service_ids, folder.as_deref())
+        .and_then(|_| state.storage.save_to_file(&app_handle));
+    let target = Some(format!("Folder of {} services", service_ids.len()));
+    state.storage.audit(&app_handle, AuditAction::Update, target, result.as_ref().err());
+    result?;
+
+    Ok(state.storage.service_views())
+}
+
+/// Fuzzy search over the issuers, accounts, tags and folders of the services, best matches
+/// first, so every frontend matches services the same way
+#[tauri::command]
+pub fn search_services(
+    app_state: State<'_, Mutex<AppState>>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<SearchMatch>, RauthyError> {
+    let state = app_state.lock().unwrap();
+    let mut matches = state.storage.search_services(&query);
+    if let Some(limit) = limit {
+        matches.truncate(limit);
+    }
+    Ok(matches)
+}
+
+/// The ids of the services in the order they're listed, with `sort` or the mode saved in
+/// the settings
+#[tauri::command]
+pub fn list_service_order(
+    app_state: State<'_, Mutex<AppState>>,
+    sort: Option<SortMode>,
+) -> Result<ServiceOrder, RauthyError> {
+    let state = app_state.lock().unwrap();
+    let sort = sort.unwrap_or(state.settings.sort_mode);
+    Ok(ServiceOrder { sort, ids: state.storage.service_order(sort) })
+}
+
+#[tauri::command]
+pub fn set_sort_mode(
+    app_state: State<'_, Mutex<AppState>>,
+    sort: SortMode,
+) -> Result<ServiceOrder, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    let mut settings = state.settings.clone();
+    settings.sort_mode = sort;
+    settings.save(&state.storage_path)?;
+    state.settings = settings;
+    Ok(ServiceOrder { sort, ids: state.storage.service_order(sort) })
+}
+
+/// Moves a service to `position` of the order arranged by the user
+#[tauri::command]
+pub fn move_service(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    service_id: String,
+    position: usize,
+) -> Result<ServiceOrder, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    state.storage.move_service(&service_id, position)?;
+    state.storage.save_to_file(&app_handle)?;
+    Ok(ServiceOrder { sort: SortMode::Manual, ids: state.storage.service_order(SortMode::Manual) })
+}
+
+/// Arranges the services in the order of `service_ids`. The ones left out keep their order
+/// after them.
+#[tauri::command]
+pub fn reorder_services(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    service_ids: Vec<String>,
+) -> Result<ServiceOrder, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    state.storage.reorder_services(&service_ids)?;
+    state.storage.save_to_file(&app_handle)?;
+    Ok(ServiceOrder { sort: SortMode::Manual, ids: state.storage.service_order(SortMode::Manual) })
+}
+
+/// The services not used for `months`, to clean up the accounts that were closed
+#[tauri::command]
+pub fn list_stale_services(
+    app_state: State<'_, Mutex<AppState>>,
+    months: u64,
+) -> Result<Vec<StaleService>, RauthyError> {
+    let state = app_state.lock().unwrap();
+    Ok(state.storage.stale_services(months))
+}
+
+/// Counts a use of the service, when its token is copied
+#[tauri::command]
+pub fn record_service_use(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    service_id: String,
+) -> Result<(), RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    state.storage.record_use(&service_id)?;
+    state.storage.save_to_file(&app_handle)
+}
+
 #[tauri::command]
 pub fn get_service_icon(
     app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
     service_id: String,
-) -> Result<String, ()> {
+) -> Result<String, RauthyError> {
     let mut state = app_state.lock().unwrap();
-    let cloned_services = state.storage.services().clone();
-    let mut service = cloned_services.get(service_id.as_str()).unwrap().clone();
+    let mut service = state
+        .storage
+        .services()
+        .get(service_id.as_str())
+        .ok_or(RauthyError::NotFound)?
+        .clone();
 
     let client_id = env!(
         "BRANDFETCH_USER_ID",
@@ -136,49 +437,68 @@ pub fn get_service_icon(
 
 #[tauri::command]
 pub fn export_services_csv(
+    app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
-) -> Result<String, String> {
-    let state = app_state.lock().unwrap();
+    password: Zeroizing<String>,
+) -> Result<String, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    // The file has every secret in plain text
+    let verified = verify_password_throttled(&app_handle, &mut state, &password);
+    if let Err(err) = &verified {
+        state.storage.audit(&app_handle, AuditAction::Export, None, Some(err));
+    }
+    verified?;
     let services = state.storage.services();
     
     if services.is_empty() {
-        return Err("No services to export".to_string());
+        return Err(RauthyError::NoServices);
     }
+    let target = Some(format!("{} services to CSV", services.len()));
+    state.storage.audit_first(&app_handle, AuditAction::Export, target)?;
 
     let mut csv_content = String::new();
     
     // CSV header
-    csv_content.push_str("Issuer,Name,Secret,Algorithm,Digits,Period,Icon\n");
+    csv_content.push_str("Issuer,Name,Secret,Algorithm,Digits,Period,Icon,Tags,Folder,Favorite\n");
     
-    // CSV data rows
-    for service in services.values() {
-        let algorithm_str = match service.algorithm {
-            totp_rs::Algorithm::SHA1 => "SHA1",
-            totp_rs::Algorithm::SHA256 => "SHA256", 
-            totp_rs::Algorithm::SHA512 => "SHA512",
-        };
-        
-        // Escape CSV fields that contain commas or quotes
-        let issuer = escape_csv_field(&service.issuer);
-        let name = escape_csv_field(&service.name);
-        let secret = escape_csv_field(&service.secret);
-        let icon = escape_csv_field(&service.icon);
-        
-        csv_content.push_str(&format!(
-            "{},{},{},{},{},{},{}\n",
-            issuer,
-            name,
-            secret,
-            algorithm_str,
-            service.digits,
-            service.period,
-            icon
-        ));
+    // CSV data rows, in the order arranged by the user, so exports can be compared
+    for service in state.storage.sorted_services(SortMode::Manual) {
+        csv_content.push_str(&csv_row(service));
     }
-    
     Ok(csv_content)
 }
 
+/// The CSV line of the service, with the fields in the order of the header
+fn csv_row(service: &Service) -> String {
+    let algorithm_str = match service.algorithm {
+        totp_rs::Algorithm::SHA1 => "SHA1",
+        totp_rs::Algorithm::SHA256 => "SHA256",
+        totp_rs::Algorithm::SHA512 => "SHA512",
+    };
+
+    // Escape CSV fields that contain commas or quotes
+    let issuer = escape_csv_field(&service.issuer);
+    let name = escape_csv_field(&service.name);
+    let secret = escape_csv_field(&service.secret);
+    let icon = escape_csv_field(&service.icon);
+    let tags = escape_csv_field(&service.tags.join(&TAG_SEPARATOR.to_string()));
+    let folder = escape_csv_field(service.folder.as_deref().unwrap_or_default());
+
+    format!(
+        "{},{},{},{},{},{},{},{},{},{}\n",
+        issuer,
+        name,
+        secret,
+        algorithm_str,
+        service.digits,
+        service.period,
+        icon,
+        tags,
+        folder,
+        service.favorite
+    )
+}
+
 fn escape_csv_field(field: &str) -> String {
     if field.contains(',') || field.contains('"') || field.contains('\n') {
         let escaped = field.replace("\"", "\"\"");
@@ -192,15 +512,15 @@ fn escape_csv_field(field: &str) -> String {
 pub fn import_services_csv(
     app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
-    csv_content: String,
-) -> Result<ServiceMap, String> {
+    csv_content: Zeroizing<String>,
+) -> Result<ServiceViewMap, RauthyError> {
     let mut state = app_state.lock().unwrap();
     let mut imported_count = 0;
     let mut errors = Vec::new();
 
     let lines: Vec<&str> = csv_content.lines().collect();
     if lines.is_empty() {
-        return Err("CSV file is empty".to_string());
+        return Err(RauthyError::InvalidCsv("CSV file is empty".to_string()));
     }
 
     // Skip header line
@@ -209,67 +529,78 @@ pub fn import_services_csv(
             continue;
         }
 
-        let fields: Vec<String> = parse_csv_line(line);
-        if fields.len() < 7 {
-            errors.push(format!("Line {}: Invalid CSV format, expected 7 fields", line_num + 1));
-            continue;
-        }
-
-        // Parse algorithm
-        let algorithm = match fields[3].to_uppercase().as_str() {
-            "SHA1" => totp_rs::Algorithm::SHA1,
-            "SHA256" => totp_rs::Algorithm::SHA256,
-            "SHA512" => totp_rs::Algorithm::SHA512,
-            _ => {
-                errors.push(format!("Line {}: Invalid algorithm '{}'", line_num + 1, fields[3]));
-                continue;
-            }
-        };
-
-        // Parse digits
-        let digits = match fields[4].parse::<usize>() {
-            Ok(d) => d,
-            Err(_) => {
-                errors.push(format!("Line {}: Invalid digits '{}'", line_num + 1, fields[4]));
+        let service = match service_from_csv_line(line) {
+            Ok(service) => service,
+            Err(err) => {
+                errors.push(format!("Line {}: {}", line_num + 1, err));
                 continue;
             }
         };
 
-        // Parse period
-        let period = match fields[5].parse::<u64>() {
-            Ok(p) => p,
-            Err(_) => {
-                errors.push(format!("Line {}: Invalid period '{}'", line_num + 1, fields[5]));
-                continue;
-            }
-        };
-
-        // Create service
-        let mut service = crate::storage::Service::default();
-        service.issuer = fields[0].clone();
-        service.name = fields[1].clone();
-        service.secret = fields[2].clone();
-        service.algorithm = algorithm;
-        service.digits = digits;
-        service.period = period;
-        service.icon = fields[6].clone();
-        service.id = format!("{}{}", service.issuer, service.name);
-
         state.storage.add_service(service);
         imported_count += 1;
     }
 
     if imported_count == 0 {
-        return Err(format!("No valid services imported. Errors: {}", errors.join("; ")));
+        let err = RauthyError::InvalidCsv(format!("No valid services imported. Errors: {}", errors.join("; ")));
+        state.storage.audit(&app_handle, AuditAction::Import, None, Some(&err));
+        return Err(err);
     }
 
     // Save the updated storage
-    state.storage.save_to_file(&app_handle).map_err(|_| "Failed to save storage".to_string())?;
+    let result = state.storage.save_to_file(&app_handle);
+    let target = Some(format!("{} services from CSV", imported_count));
+    state.storage.audit(&app_handle, AuditAction::Import, target, result.as_ref().err());
+    result?;
+    refresh_tokens(&app_handle);
 
-    let services = state.storage.services().clone();
+    let services = state.storage.service_views();
     Ok(services)
 }
 
+/// Reads a line written by [`csv_row`], or by older versions, which end at the icon
+fn service_from_csv_line(line: &str) -> Result<Service, String> {
+    let fields = Zeroizing::new(parse_csv_line(line));
+    if fields.len() < 7 {
+        return Err(String::from("Invalid CSV format, expected at least 7 fields"));
+    }
+
+    // Parse algorithm
+    let algorithm = match fields[3].to_uppercase().as_str() {
+        "SHA1" => totp_rs::Algorithm::SHA1,
+        "SHA256" => totp_rs::Algorithm::SHA256,
+        "SHA512" => totp_rs::Algorithm::SHA512,
+        _ => return Err(format!("Invalid algorithm '{}'", fields[3])),
+    };
+
+    // Parse digits
+    let digits = fields[4].parse::<usize>().map_err(|_| format!("Invalid digits '{}'", fields[4]))?;
+
+    // Parse period
+    let period = fields[5].parse::<u64>().map_err(|_| format!("Invalid period '{}'", fields[5]))?;
+
+    // Create service
+    let mut service = Service {
+        issuer: fields[0].clone(),
+        name: fields[1].clone(),
+        secret: Zeroizing::new(fields[2].clone()),
+        algorithm,
+        digits,
+        period,
+        icon: fields[6].clone(),
+        folder: fields.get(8).and_then(|folder| organize::normalize_folder(folder)),
+        favorite: fields.get(9).is_some_and(|favorite| favorite.eq_ignore_ascii_case("true")),
+        ..Default::default()
+    };
+    if let Some(tags) = fields.get(7) {
+        service.tags = organize::parse_tags(tags);
+    }
+    service.id = format!("{}{}", service.issuer, service.name);
+
+    service.validate().map_err(|err| err.to_string())?;
+    Ok(service)
+}
+
 fn parse_csv_line(line: &str) -> Vec<String> {
     let mut fields = Vec::new();
     let mut current_field = String::new();
@@ -307,65 +638,557 @@ fn parse_csv_line(line: &str) -> Vec<String> {
     fields
 }
 
+/// Replaces the password of the open vault. The current `password` is checked first.
 #[tauri::command]
 pub fn change_password(
     app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
-    mut new_password: String,
-) -> Result<(), String> {
+    password: Zeroizing<String>,
+    new_password: Zeroizing<String>,
+    key_file: Option<String>,
+) -> Result<(), RauthyError> {
     let mut state = app_state.lock().unwrap();
-    
-    // Get current services from already decrypted storage
-    let services = state.storage.services().clone();
-    
-    if services.is_empty() {
-        return Err("No services to migrate".to_string());
+    let verified = verify_password_throttled(&app_handle, &mut state, &password);
+    if let Err(err) = &verified {
+        state.storage.audit(&app_handle, AuditAction::PasswordChange, None, Some(err));
     }
+    verified?;
+    let key_file = key_file.map(|path| read_key_file(Path::new(&path))).transpose()?;
 
     // Create backup of current file
     let current_path = state.storage.storage_path(&app_handle);
     let backup_path = current_path.with_extension("bin.backup");
     
     if current_path.exists() {
-        std::fs::copy(&current_path, &backup_path)
-            .map_err(|_| "Failed to create backup file".to_string())?;
+        std::fs::copy(&current_path, &backup_path)?;
     }
 
-    // Generate new salt and key
-    let new_salt = generate_salt();
-    let new_key = derive_key_from_password_and_salt(&new_password, Some(&new_salt))
-        .map_err(|_| "Failed to generate new key".to_string())?;
+    // Only the password slot changes: the data key, and so the services, stay the same.
+    // Without a key file, the vault stops requiring one
+    let result = state
+        .storage
+        .set_password(&new_password, key_file)
+        .and_then(|_| state.storage.save_to_file(&app_handle));
+    state.storage.audit(&app_handle, AuditAction::PasswordChange, None, result.as_ref().err());
+    result?;
+    reset_failed_attempts(&app_handle, &state.storage)?;
 
-    // Create new storage with new password
-    let mut new_storage = Storage::new(new_key.to_vec(), Some(new_salt));
-    
-    // Copy all services to new storage
-    for (_, service) in services {
-        new_storage.add_service(service);
+    // The password is wiped from memory when dropped
+    Ok(())
+}
+
+/// Merges the changes made to the vault on other devices (see `Storage::sync`), and with
+/// the WebDAV server, when it's configured. The frontend calls it when the window gets the
+/// focus. The merged services are sent with the `vault-synced` event.
+#[tauri::command]
+pub fn sync_vault(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+) -> Result<SyncReport, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    let mut report = state.storage.sync(&app_handle)?;
+    if state.storage.webdav().is_some() {
+        report.extend(state.storage.sync_webdav(&app_handle)?);
+    }
+    if !report.is_empty() {
+        refresh_tokens(&app_handle);
     }
+    Ok(report)
+}
 
-    // Save with new password
-    new_storage.save_to_file(&app_handle)
-        .map_err(|_| "Failed to save with new password".to_string())?;
+/// Syncs the open vault with a file on a WebDAV server (e.g. Nextcloud), and keeps syncing
+/// it with `sync_vault`. When the first sync fails (e.g. a wrong password), the previous
+/// server is kept. `vault_password` is checked first, as the vault is sent to the server.
+#[tauri::command]
+pub fn set_webdav_sync(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    url: String,
+    username: String,
+    password: Zeroizing<String>,
+    vault_password: Zeroizing<String>,
+) -> Result<SyncReport, RauthyError> {
+    let config = WebDavConfig { url: url.trim().to_string(), username, password };
+    config.validate()?;
 
-    // Update app state
-    state.storage = new_storage;
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &vault_password)?;
+    let previous = state.storage.webdav().cloned();
+    state.storage.set_webdav(Some(config));
+    // Saved first, so the uploaded vault keeps syncing when it's downloaded on other devices
+    state.storage.save_to_file(&app_handle)?;
+    match state.storage.sync_webdav(&app_handle) {
+        Ok(report) => {
+            if !report.is_empty() {
+                refresh_tokens(&app_handle);
+            }
+            Ok(report)
+        }
+        Err(err) => {
+            state.storage.set_webdav(previous);
+            state.storage.save_to_file(&app_handle)?;
+            Err(err)
+        }
+    }
+}
 
-    // Clear password from memory
-    new_password.clear();
+#[tauri::command]
+pub fn disable_webdav_sync(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    vault_password: Zeroizing<String>,
+) -> Result<(), RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &vault_password)?;
+    state.storage.set_webdav(None);
+    state.storage.save_to_file(&app_handle)
+}
 
-    Ok(())
+/// Creates the key pair that identifies the user in team vaults, protected by its own
+/// password. A device has a single identity.
+#[tauri::command]
+pub fn create_identity(
+    app_state: State<'_, Mutex<AppState>>,
+    name: String,
+    password: Zeroizing<String>,
+) -> Result<IdentityView, RauthyError> {
+    let state = app_state.lock().unwrap();
+    if Identity::load(&state.storage_path).is_some() {
+        return Err(RauthyError::Duplicate);
+    }
+    let identity = Identity::create(&name, &password)?;
+    identity.save(&state.storage_path)?;
+    Ok(identity.view())
+}
+
+/// The identity of this device, with the public key to give to the team
+#[tauri::command]
+pub fn get_identity(app_state: State<'_, Mutex<AppState>>) -> Option<IdentityView> {
+    let state = app_state.lock().unwrap();
+    Identity::load(&state.storage_path).map(|identity| identity.view())
+}
+
+#[tauri::command]
+pub fn list_members(app_state: State<'_, Mutex<AppState>>) -> Result<Vec<TeamMember>, RauthyError> {
+    let state = app_state.lock().unwrap();
+    if !state.storage.is_unlocked() {
+        return Err(RauthyError::VaultLocked);
+    }
+    Ok(state.storage.members().to_vec())
+}
+
+/// Adds a member to the team vault, after checking the password: the member can read every
+/// secret from then on.
+#[tauri::command]
+pub fn add_member(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    name: String,
+    public_key: String,
+    password: Zeroizing<String>,
+) -> Result<Vec<TeamMember>, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &password)?;
+    state.storage.add_member(&name, &public_key)?;
+    state.storage.save_to_file(&app_handle)?;
+    Ok(state.storage.members().to_vec())
+}
+
+/// Removes a member and re-keys the vault. The new recovery key is returned by
+/// `take_recovery_key`, as usual.
+#[tauri::command]
+pub fn remove_member(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    public_key: String,
+    password: Zeroizing<String>,
+) -> Result<Vec<TeamMember>, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &password)?;
+    let previous_key = state.storage.remove_member(&public_key, &password)?;
+    state.storage.save_to_file(&app_handle)?;
+    reset_failed_attempts(&app_handle, &state.storage)?;
+    if let Err(err) = state.storage.rewrap_audit_log(&app_handle, &previous_key) {
+        eprintln!("Couldn't re-key the audit log: {err}");
+    }
+    Ok(state.storage.members().to_vec())
+}
+
+/// The audit log of the open vault. `intact` is false when lines were edited or removed.
+#[tauri::command]
+pub fn get_audit_log(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+) -> Result<AuditTrail, RauthyError> {
+    let state = app_state.lock().unwrap();
+    state.storage.read_audit_log(&app_handle)
+}
+
+/// The audit log of the open vault as JSON, to be saved by the frontend. The export is
+/// recorded in the log too.
+#[tauri::command]
+pub fn export_audit_log(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+) -> Result<String, RauthyError> {
+    let state = app_state.lock().unwrap();
+    state.storage.audit_first(&app_handle, AuditAction::Export, Some(String::from("Audit log")))?;
+    let trail = state.storage.read_audit_log(&app_handle)?;
+    serde_json::to_string_pretty(&trail).map_err(|err| RauthyError::Io(err.to_string()))
+}
+
+/// The WebDAV server the open vault is synced to, without its password
+#[tauri::command]
+pub fn get_webdav_sync(app_state: State<'_, Mutex<AppState>>) -> Option<WebDavStatus> {
+    let state = app_state.lock().unwrap();
+    state.storage.webdav().map(WebDavStatus::from)
+}
+
+/// Downloads a vault synced to a WebDAV server by another device, as a new vault in the data
+/// dir. It's unlocked with `open_vault` and the password of that vault, and keeps syncing.
+#[tauri::command]
+pub fn download_webdav_vault(
+    app_state: State<'_, Mutex<AppState>>,
+    name: String,
+    url: String,
+    username: String,
+    password: Zeroizing<String>,
+) -> Result<VaultRegistry, RauthyError> {
+    let config = WebDavConfig { url: url.trim().to_string(), username, password };
+    config.validate()?;
+    let content = match WebDavClient::new(&config).get(None)? {
+        Fetched::Found { content, .. } => content,
+        _ => return Err(RauthyError::NotFound),
+    };
+    VaultFile::decode(&content)?.ok_or(RauthyError::CorruptVault)?;
+
+    let mut state = app_state.lock().unwrap();
+    let mut vaults = state.vaults.clone();
+    let vault = vaults.add(&name, None)?.clone();
+    let vault_path = state.vault_path(&vault);
+    std::fs::create_dir_all(&state.storage_path)?;
+    std::fs::write(&vault_path, content)?;
+    vaults.save(&state.storage_path)?;
+    state.vaults = vaults;
+    Ok(state.vaults.clone())
+}
+
+/// Starts sending the services with `service_ids` to another device on the local network.
+/// The other device scans the returned QR code; the services are sent once the user
+/// confirms both devices show the same code, with `confirm_transfer`. The progress is sent
+/// with the `transfer-paired` and `transfer-finished` events.
+#[tauri::command]
+pub fn start_transfer(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    service_ids: Vec<String>,
+    password: Zeroizing<String>,
+) -> Result<TransferOffer, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    if !state.storage.is_unlocked() {
+        return Err(RauthyError::VaultLocked);
+    }
+    verify_password_throttled(&app_handle, &mut state, &password)?;
+    let services = service_ids
+        .iter()
+        .map(|id| state.storage.services().get(id).cloned().ok_or(RauthyError::NotFound))
+        .collect::<Result<Vec<_>, _>>()?;
+    if services.is_empty() {
+        return Err(RauthyError::NoServices);
+    }
+    let target = Some(format!("{} services to another device", services.len()));
+    state.storage.audit_first(&app_handle, AuditAction::Export, target.clone())?;
+    // A failed transfer is recorded too, in the log of this vault
+    let member = state.storage.identity().map(|identity| identity.name.clone());
+    let audit_log = state.storage.audit_log(&app_handle);
+    drop(state);
+
+    transfer::start_sending(&app_handle, services, move |result| {
+        let Err(error) = result else {
+            return;
+        };
+        if let Err(err) = audit_log.append(&AuditEvent::new(AuditAction::Export, target, member, Some(error))) {
+            eprintln!("Couldn't write the audit log: {err}");
+        }
+    })
+}
+
+/// Sends the services of the pending transfer, or cancels it when the codes don't match.
+/// Sending needs the password typed again, so the services can't be sent without the user
+/// checking the codes.
+#[tauri::command]
+pub fn confirm_transfer(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    pending: State<'_, PendingTransfer>,
+    confirmed: bool,
+    password: Option<Zeroizing<String>>,
+) -> Result<(), RauthyError> {
+    if confirmed {
+        let password = password.ok_or(RauthyError::PasswordRequired)?;
+        let mut state = app_state.lock().unwrap();
+        verify_password_throttled(&app_handle, &mut state, &password)?;
+    }
+    pending.confirm(confirmed)
 }
 
+/// Receives the services sent by the device that shows the QR code with `uri` into the open
+/// vault. The progress is sent with the `transfer-paired` and `transfer-finished` events.
+#[tauri::command]
+pub fn receive_transfer(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    uri: String,
+) -> Result<(), RauthyError> {
+    if !app_state.lock().unwrap().storage.is_unlocked() {
+        return Err(RauthyError::VaultLocked);
+    }
+    transfer::start_receiving(&app_handle, &uri)
+}
+
+/// Closes the open vault
 #[tauri::command]
 pub fn close_services_file(
+    app_handle: tauri::AppHandle,
+) -> Result<(), RauthyError> {
+    // Wipes the key and services from memory and resets to default state
+    lock_vault(&app_handle, LockReason::Manual);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_vaults(app_state: State<'_, Mutex<AppState>>) -> VaultRegistry {
+    let state = app_state.lock().unwrap();
+    state.vaults.clone()
+}
+
+/// Creates a new, empty vault with its own password (and key file) and opens it. The open
+/// vault is closed first. Its recovery key is returned by `take_recovery_key`, as usual.
+///
+/// The file is created in `location` (e.g. a USB stick or an encrypted volume) or in the data dir.
+#[tauri::command]
+pub fn create_vault(
+    app_handle: tauri::AppHandle,
     app_state: State<'_, Mutex<AppState>>,
-) -> Result<(), String> {
+    name: String,
+    password: Zeroizing<String>,
+    key_file: Option<String>,
+    location: Option<String>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let key_file = key_file.map(|path| read_key_file(Path::new(&path))).transpose()?;
+    lock_vault(&app_handle, LockReason::Manual);
+
     let mut state = app_state.lock().unwrap();
-    
-    // Clear the storage and reset to default state
-    state.storage = crate::storage::Storage::new(Vec::new(), None);
-    
+    let mut vaults = state.vaults.clone();
+    let vault = vaults.add(&name, location.as_deref().map(Path::new))?.clone();
+    vaults.set_active(&vault.id)?;
+
+    let vault_path = state.vault_path(&vault);
+    let mut storage = Storage::create(&vault_path, &password, key_file)?;
+    storage.save_to_file(&app_handle)?;
+    vaults.save(&state.storage_path)?;
+    state.vaults = vaults;
+
+    Ok(set_unlocked_storage(&app_handle, &mut state, storage))
+}
+
+/// Like `create_vault`, but the vault is shared with a team: it's opened with the identity of
+/// each member instead of a password, starting with the identity of this device, whose
+/// password is checked here.
+#[tauri::command]
+pub fn create_team_vault(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    name: String,
+    password: Zeroizing<String>,
+    location: Option<String>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let identity = {
+        let state = app_state.lock().unwrap();
+        Identity::load(&state.storage_path).ok_or(RauthyError::IdentityRequired)?
+    };
+    identity.unlock(&password)?;
+    lock_vault(&app_handle, LockReason::Manual);
+
+    let mut state = app_state.lock().unwrap();
+    let mut vaults = state.vaults.clone();
+    let vault = vaults.add(&name, location.as_deref().map(Path::new))?.clone();
+    vaults.set_active(&vault.id)?;
+
+    let vault_path = state.vault_path(&vault);
+    let mut storage = Storage::create_team(&vault_path, &identity)?;
+    storage.save_to_file(&app_handle)?;
+    vaults.save(&state.storage_path)?;
+    state.vaults = vaults;
+
+    Ok(set_unlocked_storage(&app_handle, &mut state, storage))
+}
+
+/// Closes the open vault and unlocks another one, which becomes the active vault
+#[tauri::command]
+pub fn open_vault(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    id: String,
+    password: Zeroizing<String>,
+    key_file: Option<String>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let key_file = key_file.map(|path| read_key_file(Path::new(&path))).transpose()?;
+    lock_vault(&app_handle, LockReason::Manual);
+
+    let mut state = app_state.lock().unwrap();
+    state.vaults.set_active(&id)?;
+    state.vaults.save(&state.storage_path)?;
+    drop(state);
+
+    fetch_services_with_pass(app_handle, app_state, password, key_file)
+}
+
+/// Adds an existing vault file to the registry, e.g. one kept on a USB stick. It's unlocked
+/// with `open_vault`, like the others. Only vaults are added, as the files of a vault can be
+/// deleted by the wipe policy. `password` is only needed for files of the first version.
+#[tauri::command]
+pub fn add_vault_file(
+    app_state: State<'_, Mutex<AppState>>,
+    name: String,
+    path: String,
+    password: Zeroizing<String>,
+) -> Result<VaultRegistry, RauthyError> {
+    let path = Path::new(&path);
+    if !path.is_file() {
+        return Err(RauthyError::NotFound);
+    }
+    check_vault_file(&std::fs::read(path)?, &password)?;
+
+    let mut state = app_state.lock().unwrap();
+    let mut vaults = state.vaults.clone();
+    vaults.attach(&name, path)?;
+    vaults.save(&state.storage_path)?;
+    state.vaults = vaults;
+    Ok(state.vaults.clone())
+}
+
+/// Moves a vault file, with the files kept next to it (see [`crate::vaults::vault_files`]),
+/// to another dir. The vault can be open: it's saved to the new file from then on.
+#[tauri::command]
+pub fn move_vault(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    id: String,
+    directory: String,
+) -> Result<VaultRegistry, RauthyError> {
+    // A relative dir would depend on the working dir the app was started from
+    let directory = Path::new(&directory);
+    if !directory.is_absolute() {
+        return Err(RauthyError::Io(String::from("The directory must be an absolute path")));
+    }
+    let mut state = app_state.lock().unwrap();
+    let vault = state.vaults.get(&id).ok_or(RauthyError::NotFound)?.clone();
+    let old_path = state.vault_path(&vault);
+    let file_name = old_path.file_name().ok_or(RauthyError::NotFound)?;
+    let new_path = directory.join(file_name);
+    if new_path == old_path {
+        return Ok(state.vaults.clone());
+    }
+    if new_path.exists() {
+        return Err(RauthyError::Duplicate);
+    }
+
+    std::fs::create_dir_all(directory)?;
+    copy_vault_files(&old_path, &new_path)?;
+
+    let mut vaults = state.vaults.clone();
+    vaults.set_path(&id, new_path.clone())?;
+    vaults.save(&state.storage_path)?;
+    state.vaults = vaults;
+    if state.storage.storage_path(&app_handle) == old_path {
+        state.storage.set_file_path(&new_path);
+    }
+    delete_vault_files(&old_path)?;
+    Ok(state.vaults.clone())
+}
+
+#[tauri::command]
+pub fn rename_vault(
+    app_state: State<'_, Mutex<AppState>>,
+    id: String,
+    name: String,
+) -> Result<(), RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    let mut vaults = state.vaults.clone();
+    vaults.rename(&id, &name)?;
+    vaults.save(&state.storage_path)?;
+    state.vaults = vaults;
+    Ok(())
+}
+
+/// Deletes a vault file, with the files kept next to it, after checking its password.
+/// If it's the open vault, it's closed.
+#[tauri::command]
+pub fn delete_vault(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    id: String,
+    password: Zeroizing<String>,
+    key_file: Option<String>,
+) -> Result<(), RauthyError> {
+    let key_file = key_file.map(|path| read_key_file(Path::new(&path))).transpose()?;
+    let mut state = app_state.lock().unwrap();
+    let vault = state.vaults.get(&id).ok_or(RauthyError::NotFound)?.clone();
+
+    let mut storage = Storage::at(&state.vault_path(&vault));
+    if storage.file_exists(&app_handle) {
+        unlock_vault(&app_handle, &state.settings, &mut storage, |storage| {
+            storage.unlock_with_password(&app_handle, &password, key_file)
+        })?;
+        let vault_path = storage.storage_path(&app_handle);
+        storage.lock();
+        delete_vault_files(&vault_path)?;
+    }
+
+    let mut vaults = state.vaults.clone();
+    vaults.remove(&id)?;
+    vaults.save(&state.storage_path)?;
+    let was_active = state.vaults.active == id;
+    state.vaults = vaults;
+    drop(state);
+
+    if was_active {
+        lock_vault(&app_handle, LockReason::Manual);
+    }
+    Ok(())
+}
+
+/// Called by the frontend when the user interacts with the app, to postpone the idle lock
+#[tauri::command]
+pub fn report_activity(app_handle: tauri::AppHandle) {
+    record_activity(&app_handle);
+}
+
+#[tauri::command]
+pub fn get_settings(app_state: State<'_, Mutex<AppState>>) -> Settings {
+    let state = app_state.lock().unwrap();
+    state.settings.clone()
+}
+
+#[tauri::command]
+pub fn update_settings(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    settings: Settings,
+    password: Option<Zeroizing<String>>,
+) -> Result<(), RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    if state.settings.security_changed(&settings) {
+        let password = password.ok_or(RauthyError::PasswordRequired)?;
+        verify_password_throttled(&app_handle, &mut state, &password)?;
+    }
+    settings.save(&state.storage_path)?;
+    state.settings = settings;
+    drop(state);
+
+    reset_idle_timer(&app_handle);
     Ok(())
 }
 
@@ -376,67 +1199,415 @@ pub fn fetch_without_pass(
     app_state: State<'_, Mutex<AppState>>,
     reason: String,
     options: AuthOptions,
-) -> Result<ServiceMap, Error> {
+) -> Result<ServiceViewMap, RauthyError> {
     use tauri_plugin_biometric::BiometricExt;
     match app_handle
         .biometric()
-        .biometric_cipher(reason, options.try_into().unwrap())
+        .biometric_cipher(reason, options.try_into().map_err(|_| RauthyError::Biometric)?)
     {
-        Ok(data) => fetch_services_with_pass(app_handle, app_state, data.data),
+        Ok(data) => {
+            let data = Zeroizing::new(data.data);
+            match data.strip_prefix(BIOMETRIC_SECRET_PREFIX) {
+                Some(secret) => fetch_services_with_biometric_secret(app_handle, app_state, secret),
+                // Older versions stored the encrypted password
+                None => fetch_services_with_pass(app_handle, app_state, data, None),
+            }
+        }
         Err(_) => {
             dbg!("Can't load biometric decrypted data.");
-            Err("Can't load biometric decrypted data.".into())
+            Err(RauthyError::Biometric)
         }
     }
 
 }
 
-pub fn fetch_services_with_pass(app_handle: tauri::AppHandle, app_state: State<'_, Mutex<AppState>>, mut user_pass: String) -> Result<ServiceMap, Error> {
+#[cfg(mobile)]
+fn fetch_services_with_biometric_secret(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    secret: &str,
+) -> Result<ServiceViewMap, RauthyError> {
     let mut state = app_state.lock().unwrap();
+    let secret = Zeroizing::new(hex::decode(secret).map_err(|_| RauthyError::Biometric)?);
+    let mut storage = state.active_storage();
+
+    unlock_vault(&app_handle, &state.settings, &mut storage, |storage| {
+        storage.unlock_with_secret(&app_handle, KeySlotKind::Biometric, &secret)
+    })?;
+    reset_failed_attempts(&app_handle, &storage)?;
 
-    // Tries to generate a key in the old format, with hardcoded salt
-    // This is for backwards compatibility with older versions
-    let key = derive_key_from_password_and_salt(user_pass.as_str(), None)?;
-    let mut storage = Storage::new(key.to_vec(), None);
+    Ok(set_unlocked_storage(&app_handle, &mut state, storage))
+}
+
+pub fn fetch_services_with_pass(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    user_pass: Zeroizing<String>,
+    key_file: Option<Zeroizing<KeyArray>>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    let mut storage = state.active_storage();
 
     if storage.file_exists(&app_handle) {
-    
-        match storage.read_from_file(&app_handle) {
-            Err(_) => {
-                // As we couldn't read the file using the old format, we will try to read it using the new format
-                // Reading the salt from the file
-                let salt = storage.read_salt_from_file(&app_handle).unwrap();
-                let key = derive_key_from_password_and_salt(user_pass.as_str(), Some(&salt))?;
-                storage = Storage::new(key.to_vec(), Some(salt));
-
-                match storage.read_from_file(&app_handle) {
-                    Err(_) => return Err("Couldn't decrypt the storage file".into()),
-                    Ok(_) => {}                
+        // Files of older versions are migrated to the current format here. Otherwise the
+        // file isn't written: the data key and the password slot stay the same
+        let data_dir = state.storage_path.clone();
+        unlock_vault(&app_handle, &state.settings, &mut storage, |storage| {
+            match storage.unlock_with_password(&app_handle, &user_pass, key_file) {
+                // Team vaults have no password: the user's own identity opens them
+                Err(RauthyError::IdentityRequired) => {
+                    let identity = Identity::load(&data_dir).ok_or(RauthyError::IdentityRequired)?;
+                    storage.unlock_with_identity(&app_handle, &identity, &user_pass)
                 }
+                result => result,
             }
-            Ok(_) => {}
+        })?;
+        reset_failed_attempts(&app_handle, &storage)?;
+    } else {
+        // If this is a new file, we will generate a new data key and recovery key
+        storage = Storage::create(&state.vault_path(state.vaults.active()), &user_pass, key_file)?;
+        storage.save_to_file(&app_handle)?;
+    }
+
+    // user_pass is a Zeroizing<String>, so the password is wiped from memory
+    // as soon as it's dropped at the end of this function
+
+    Ok(set_unlocked_storage(&app_handle, &mut state, storage))
+}
+
+fn set_unlocked_storage(app_handle: &tauri::AppHandle, state: &mut AppState, storage: Storage) -> ServiceViewMap {
+    state.storage = storage;
+    // New vaults get their audit log here
+    if let Err(err) = state.storage.init_audit_log(app_handle) {
+        eprintln!("Couldn't create the audit log: {err}");
+    }
+    // Merges the conflict copies left by sync tools while the vault was closed. The vault
+    // is open anyway, so a failure is only logged
+    if let Err(err) = state.storage.sync(app_handle) {
+        eprintln!("Couldn't merge the changes of other devices: {err}");
+    }
+    if state.storage.webdav().is_some() {
+        if let Err(err) = state.storage.sync_webdav(app_handle) {
+            eprintln!("Couldn't sync with the WebDAV server: {err}");
         }
+    }
+    refresh_tokens(app_handle);
+    reset_idle_timer(app_handle);
 
-        // Now that we already read the file, we will generate a new salt and key, and change the key for encryption
-        let new_salt = crate::crypto::generate_salt();
-        let new_key = crate::crypto::derive_key_from_password_and_salt(user_pass.as_str(), Some(&new_salt))?;
-        storage.set_new_key_and_salt(new_key.to_vec(), new_salt);
-        storage.save_to_file(&app_handle).unwrap();
+    state.storage.service_views()
+}
 
-    } else {
-        // If this is a new file, we will generate a new salt
-        let salt = crate::crypto::generate_salt();
-        let key = crate::crypto::derive_key_from_password_and_salt(user_pass.as_str(), Some(&salt))?;
-        storage = Storage::new(key.to_vec(), Some(salt));
+/// Unlocks the vault with the recovery key, when the password (or the key file) was lost,
+/// and protects it with a new password and, optionally, a key file.
+#[tauri::command]
+pub fn unlock_with_recovery_key(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    recovery_key: Zeroizing<String>,
+    new_password: Zeroizing<String>,
+    key_file: Option<String>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let recovery_key = parse_recovery_key(&recovery_key)?;
+    reset_password_with_recovery_key(app_handle, app_state, &recovery_key, new_password, key_file)
+}
+
+/// Same as `unlock_with_recovery_key`, with the recovery key rebuilt from the shares
+/// created by `split_recovery_key`.
+#[tauri::command]
+pub fn unlock_with_recovery_shares(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    shares: Vec<Zeroizing<String>>,
+    new_password: Zeroizing<String>,
+    key_file: Option<String>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let shares = shares
+        .iter()
+        .map(|share| parse_share(share))
+        .collect::<Result<Vec<_>, _>>()?;
+    let recovery_key = combine_shares(&shares)?;
+    reset_password_with_recovery_key(app_handle, app_state, &recovery_key, new_password, key_file)
+}
+
+fn reset_password_with_recovery_key(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    recovery_key: &[u8],
+    new_password: Zeroizing<String>,
+    key_file: Option<String>,
+) -> Result<ServiceViewMap, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    let key_file = key_file.map(|path| read_key_file(Path::new(&path))).transpose()?;
+    let mut storage = state.active_storage();
+
+    unlock_vault(&app_handle, &state.settings, &mut storage, |storage| {
+        storage
+            .unlock_with_secret(&app_handle, KeySlotKind::Recovery, recovery_key)
+            .map_err(|err| match err {
+                RauthyError::WrongPassword | RauthyError::NotFound => RauthyError::InvalidRecoveryKey,
+                err => err,
+            })
+    })?;
+    let result = storage
+        .set_password(&new_password, key_file)
+        .and_then(|_| storage.save_to_file(&app_handle));
+    let target = Some(String::from("With the recovery key"));
+    storage.audit(&app_handle, AuditAction::PasswordChange, target, result.as_ref().err());
+    result?;
+    reset_failed_attempts(&app_handle, &storage)?;
+
+    Ok(set_unlocked_storage(&app_handle, &mut state, storage))
+}
+
+/// Returns the recovery key generated when the vault was created, so the frontend can show
+/// it. It's only returned once.
+#[tauri::command]
+pub fn take_recovery_key(app_state: State<'_, Mutex<AppState>>) -> Option<Zeroizing<String>> {
+    let mut state = app_state.lock().unwrap();
+    state.storage.take_recovery_key()
+}
+
+/// Replaces the recovery key (e.g. when the printed one was lost) and returns the new one.
+#[tauri::command]
+pub fn regenerate_recovery_key(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    password: Zeroizing<String>,
+) -> Result<Zeroizing<String>, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &password)?;
+
+    let recovery_key = state.storage.new_recovery_key()?;
+    state.storage.save_to_file(&app_handle)?;
+    Ok(recovery_key)
+}
+
+/// Writes a new random key file, to be used with `setup_storage_keys` and `change_password`.
+#[tauri::command]
+pub fn generate_key_file(path: String) -> Result<(), RauthyError> {
+    keyfile::generate_key_file(Path::new(&path))
+}
+
+/// Adds a biometric slot to the vault, wrapping the data key with a new random secret.
+///
+/// The frontend encrypts the returned secret with the device biometric keystore and sends it
+/// back to `fetch_without_pass`, so the password is never stored on the device. The secret
+/// unlocks the vault, so the password is checked first.
+#[cfg(mobile)]
+#[tauri::command]
+pub fn enable_biometric_unlock(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    password: Zeroizing<String>,
+) -> Result<Zeroizing<String>, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &password)?;
+    let secret = generate_key();
+    state.storage.set_secret(KeySlotKind::Biometric, secret.as_ref())?;
+    state.storage.save_to_file(&app_handle)?;
+
+    Ok(Zeroizing::new(format!("{}{}", BIOMETRIC_SECRET_PREFIX, hex::encode(secret.as_ref()))))
+}
+
+#[cfg(mobile)]
+#[tauri::command]
+pub fn disable_biometric_unlock(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    password: Zeroizing<String>,
+) -> Result<(), RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &password)?;
+    state.storage.remove_key_slot(KeySlotKind::Biometric)?;
+    state.storage.save_to_file(&app_handle)
+}
+
+/// Replaces the recovery key with a new one split into `count` shares, any `threshold` of
+/// them needed to rebuild it. The whole key is never shown, so no single holder of a share
+/// can unlock the vault. The previous recovery key (or shares) stop working.
+#[tauri::command]
+pub fn split_recovery_key(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    password: Zeroizing<String>,
+    count: u8,
+    threshold: u8,
+) -> Result<Vec<RecoveryShare>, RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &password)?;
+
+    let recovery_key = generate_recovery_key();
+    let shares = split_secret(&recovery_key, count, threshold)?
+        .iter()
+        .map(RecoveryShare::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    state.storage.set_secret(KeySlotKind::Recovery, &recovery_key)?;
+    state.storage.save_to_file(&app_handle)?;
+    Ok(shares)
+}
+
+/// Sets a second password that opens a decoy set of services instead of the real ones,
+/// to be given up under coercion. The file looks the same with or without it.
+#[tauri::command]
+pub fn set_duress_password(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    password: Zeroizing<String>,
+    duress_password: Zeroizing<String>,
+) -> Result<(), RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &password)?;
+    state.storage.set_duress_password(&duress_password)?;
+    state.storage.save_to_file(&app_handle)
+}
+
+#[tauri::command]
+pub fn remove_duress_password(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, Mutex<AppState>>,
+    password: Zeroizing<String>,
+) -> Result<(), RauthyError> {
+    let mut state = app_state.lock().unwrap();
+    verify_password_throttled(&app_handle, &mut state, &password)?;
+    state.storage.remove_duress_password()?;
+    state.storage.save_to_file(&app_handle)
+}
+
+/// Runs `unlock` unless the user must still wait after the previous failed attempts.
+/// A wrong password or recovery key is counted as a failed attempt.
+fn unlock_vault(
+    app_handle: &tauri::AppHandle,
+    settings: &Settings,
+    storage: &mut Storage,
+    unlock: impl FnOnce(&mut Storage) -> Result<(), RauthyError>,
+) -> Result<(), RauthyError> {
+    let vault_path = storage.storage_path(app_handle);
+    let key = attempts_key(app_handle)?;
+    let attempts = FailedAttempts::load(&FailedAttempts::path(&vault_path), &key);
+
+    let wait_seconds = attempts.wait_seconds(unix_now());
+    if wait_seconds > 0 {
+        emit_throttled(app_handle, &attempts, wait_seconds);
+        let err = RauthyError::TooManyAttempts(wait_seconds);
+        storage.audit(app_handle, AuditAction::Unlock, None, Some(&err));
+        return Err(err);
     }
 
-    // clear the user_pass in memory
-    // This is important for security reasons
-    // to prevent the password from being stored in memory longer than necessary
-    user_pass.zeroize();
+    let result = unlock(storage);
+    if result.is_ok() {
+        // Vaults created by older versions get their log here
+        if let Err(err) = storage.init_audit_log(app_handle) {
+            eprintln!("Couldn't create the audit log: {err}");
+        }
+    }
+    storage.audit(app_handle, AuditAction::Unlock, None, result.as_ref().err());
+    match result {
+        Err(err @ (RauthyError::WrongPassword | RauthyError::InvalidRecoveryKey)) => Err(
+            register_failed_attempt(app_handle, settings, &vault_path, attempts, &key, err),
+        ),
+        result => result,
+    }
+}
 
-    state.storage = storage;
+/// Checks the password typed again to authorize a sensitive operation on the open vault.
+/// The checks share the failed attempts of the unlock screen, so they can't be used to
+/// guess the password without waiting, and count towards the wipe policy.
+fn verify_password_throttled(
+    app_handle: &tauri::AppHandle,
+    state: &mut AppState,
+    password: &str,
+) -> Result<(), RauthyError> {
+    let vault_path = state.storage.storage_path(app_handle);
+    let key = attempts_key(app_handle)?;
+    let attempts_path = FailedAttempts::path(&vault_path);
+    let attempts = FailedAttempts::load(&attempts_path, &key);
 
-    let services = state.storage.services().clone();
-    Ok(services)
+    let wait_seconds = attempts.wait_seconds(unix_now());
+    if wait_seconds > 0 {
+        emit_throttled(app_handle, &attempts, wait_seconds);
+        return Err(RauthyError::TooManyAttempts(wait_seconds));
+    }
+
+    match state.storage.verify_password(password) {
+        Ok(()) if attempts != FailedAttempts::default() => FailedAttempts::reset(&attempts_path, &key),
+        Ok(()) => Ok(()),
+        Err(err @ RauthyError::WrongPassword) => {
+            let err = register_failed_attempt(app_handle, &state.settings, &vault_path, attempts, &key, err);
+            if matches!(err, RauthyError::VaultWiped) {
+                state.storage.lock();
+            }
+            Err(err)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Clears the failed attempts after the vault was unlocked, or after its password changed
+fn reset_failed_attempts(app_handle: &tauri::AppHandle, storage: &Storage) -> Result<(), RauthyError> {
+    let key = attempts_key(app_handle)?;
+    FailedAttempts::reset(&FailedAttempts::path(&storage.storage_path(app_handle)), &key)
+}
+
+/// The key of the failed attempts files of this device
+fn attempts_key(app_handle: &tauri::AppHandle) -> Result<Zeroizing<KeyArray>, RauthyError> {
+    device_key(&data_dir(app_handle))
+}
+
+/// Persists a failed unlock and tells the frontend how long to wait before the next one.
+/// When the wipe policy is enabled and the limit is reached, the vault and its backup are deleted.
+fn register_failed_attempt(
+    app_handle: &tauri::AppHandle,
+    settings: &Settings,
+    vault_path: &Path,
+    mut attempts: FailedAttempts,
+    key: &KeyArray,
+    error: RauthyError,
+) -> RauthyError {
+    let now = unix_now();
+    attempts.record_failure(now);
+    let attempts_path = FailedAttempts::path(vault_path);
+
+    if settings.wipe_after_failed_attempts > 0 && attempts.count >= settings.wipe_after_failed_attempts {
+        return match delete_vault_files(vault_path) {
+            Ok(()) => RauthyError::VaultWiped,
+            Err(err) => err,
+        };
+    }
+
+    if let Err(err) = attempts.save(&attempts_path, key) {
+        return err;
+    }
+    emit_throttled(app_handle, &attempts, attempts.wait_seconds(now));
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_keeps_tags_folder_and_favorite() {
+        let service = Service {
+            id: String::from("GitHubalice"),
+            issuer: String::from("GitHub"),
+            name: String::from("alice"),
+            secret: Zeroizing::new(String::from("JBSWY3DPEHPK3PXP")),
+            tags: vec![String::from("dev, oss"), String::from("work")],
+            folder: Some(String::from("Code")),
+            favorite: true,
+            ..Default::default()
+        };
+
+        let imported = service_from_csv_line(&csv_row(&service)).unwrap();
+        assert_eq!(imported.id, service.id);
+        assert_eq!(imported.secret, service.secret);
+        assert_eq!(imported.tags, service.tags);
+        assert_eq!(imported.folder, service.folder);
+        assert!(imported.favorite);
+
+        // Exported by older versions
+        let imported = service_from_csv_line("GitHub,alice,JBSWY3DPEHPK3PXP,SHA1,6,30,").unwrap();
+        assert!(imported.tags.is_empty() && imported.folder.is_none() && !imported.favorite);
+    }
 }
@@ -3,16 +3,23 @@ use aes_gcm::{
     Aes256Gcm, Key, KeyInit, Nonce,
 };
 
-use ring::{digest, pbkdf2};
+use ring::{digest, hkdf, pbkdf2};
 use rand::RngCore;
 use std::num::NonZeroU32;
+use zeroize::Zeroizing;
+
+use crate::error::RauthyError;
 
 const CREDENTIAL_LEN: usize = digest::SHA256_OUTPUT_LEN;
 pub const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
 pub const SALT: &str = "E3D0C30656C194272C7B6AD2ED0B7F8078FF2921F777A142A045D45931BC2771";
 pub type SaltArray = [u8; SALT_LEN];
 pub type KeyArray = [u8; CREDENTIAL_LEN];
-pub type Error = &'static str;
+pub type Error = RauthyError;
+
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const HKDF_INFO: &[u8] = b"rauthy-key-encryption-key";
 
 /// Gera um salt aleatório de 32 bytes.
 pub fn generate_salt() -> SaltArray {
@@ -35,19 +42,72 @@ pub fn generate_salt() -> SaltArray {
 /// let salt = generate_salt();
 /// let key = derive_key_from_password_and_salt(user_pass, &salt).unwrap();
 ///
-pub fn derive_key_from_password_and_salt(user_pass: &str, salt: Option<&[u8]>) -> Result<KeyArray, Error> {
+/// The key is wiped from memory when dropped.
+pub fn derive_key_from_password_and_salt(user_pass: &str, salt: Option<&[u8]>) -> Result<Zeroizing<KeyArray>, Error> {
     let old_salt = &data_encoding::HEXUPPER.decode(SALT.as_bytes()).unwrap();
     let salt = salt.unwrap_or(&old_salt);
-    let n_iter = NonZeroU32::new(100_000).unwrap();
-    let mut pbkdf2_hash = [0u8; CREDENTIAL_LEN];
+    Ok(pbkdf2_derive(user_pass.as_bytes(), salt))
+}
+
+/// Derivates a key from the user pass combined with the hash of a key file, so the
+/// vault can't be opened with only one of them. Like KeePass, the PBKDF2 input is
+/// `SHA-256(user_pass) || key_file_hash`.
+///
+/// The key is wiped from memory when dropped.
+pub fn derive_key_from_password_and_key_file(
+    user_pass: &str,
+    key_file_hash: &KeyArray,
+    salt: &[u8],
+) -> Result<Zeroizing<KeyArray>, Error> {
+    let mut input = Zeroizing::new(Vec::with_capacity(2 * CREDENTIAL_LEN));
+    input.extend_from_slice(digest::digest(&digest::SHA256, user_pass.as_bytes()).as_ref());
+    input.extend_from_slice(key_file_hash);
+    Ok(pbkdf2_derive(&input, salt))
+}
+
+fn pbkdf2_derive(secret: &[u8], salt: &[u8]) -> Zeroizing<KeyArray> {
+    let n_iter = NonZeroU32::new(PBKDF2_ITERATIONS).unwrap();
+    let mut pbkdf2_hash = Zeroizing::new([0u8; CREDENTIAL_LEN]);
     pbkdf2::derive(
         pbkdf2::PBKDF2_HMAC_SHA256,
         n_iter,
         salt,
-        user_pass.as_bytes(),
-        &mut pbkdf2_hash,
+        secret,
+        pbkdf2_hash.as_mut(),
     );
-    Ok(pbkdf2_hash)
+    pbkdf2_hash
+}
+
+/// Hashes the content of a key file. Any file can be a key file: only its hash is used.
+pub fn hash_key_file(content: &[u8]) -> Zeroizing<KeyArray> {
+    let mut hash = Zeroizing::new([0u8; CREDENTIAL_LEN]);
+    hash.copy_from_slice(digest::digest(&digest::SHA256, content).as_ref());
+    hash
+}
+
+/// Derivates a key from a high-entropy secret (e.g. the recovery key) and a salt, using HKDF.
+///
+/// Unlike passwords, these secrets can't be guessed, so they don't need a slow KDF.
+///
+/// The key is wiped from memory when dropped.
+pub fn derive_key_from_secret_and_salt(secret: &[u8], salt: &[u8]) -> Result<Zeroizing<KeyArray>, Error> {
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(secret);
+    let okm = prk
+        .expand(&[HKDF_INFO], hkdf::HKDF_SHA256)
+        .map_err(|_| RauthyError::Crypto("Couldn't derive the key"))?;
+    let mut key = Zeroizing::new([0u8; CREDENTIAL_LEN]);
+    okm.fill(key.as_mut())
+        .map_err(|_| RauthyError::Crypto("Couldn't derive the key"))?;
+    Ok(key)
+}
+
+/// Generates a random 256 bits key, used to encrypt the vault data.
+///
+/// The key is wiped from memory when dropped.
+pub fn generate_key() -> Zeroizing<KeyArray> {
+    let mut key = Zeroizing::new([0u8; CREDENTIAL_LEN]);
+    rand::rng().fill_bytes(key.as_mut());
+    key
 }
 
 /// Encrypts the given data using AES-256-GCM with a random nonce.
@@ -58,7 +118,7 @@ pub fn derive_key_from_password_and_salt(user_pass: &str, salt: Option<&[u8]>) -
 ///
 /// # Arguments
 ///
-/// * `data` - The data to be encrypted as a vector of bytes.
+/// * `data` - The data to be encrypted as a slice of bytes.
 /// * `key` - The encryption key as a 32-byte array.
 ///
 /// # Returns
@@ -67,8 +127,8 @@ pub fn derive_key_from_password_and_salt(user_pass: &str, salt: Option<&[u8]>) -
 ///
 /// # Errors
 ///
-/// This function will panic if the encryption operation fails.
-pub fn encrypt_data(data: Vec<u8>, key: &[u8]) -> Result<Vec<u8>, Error> {
+/// Returns `RauthyError::Crypto` if the encryption operation fails.
+pub fn encrypt_data(data: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
     let key = Key::<Aes256Gcm>::from_slice(key);
     let cipher = Aes256Gcm::new(&key);
     let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
@@ -77,11 +137,11 @@ pub fn encrypt_data(data: Vec<u8>, key: &[u8]) -> Result<Vec<u8>, Error> {
         .encrypt(
             &nonce,
             Payload {
-                msg: data.as_ref(),
+                msg: data,
                 aad: b"",
             },
         )
-        .unwrap(); // Encrypt the data using GCM
+        .map_err(|_| RauthyError::Crypto("Couldn't encrypt the data"))?; // Encrypt the data using GCM
                    // println!("Encrypted data: {:?}, Len: {:?}", encrypted_data, encrypted_data.len());
                    // println!("Nonce: {:?}, Len: {:?}", nonce, nonce.len());
     Ok([nonce.to_vec(), encrypted_data].concat()) // Prepend nonce to the encrypted data
@@ -94,19 +154,23 @@ pub fn encrypt_data(data: Vec<u8>, key: &[u8]) -> Result<Vec<u8>, Error> {
 ///
 /// # Arguments
 ///
-/// * `data` - A vector of bytes with the 96bits nonce + the emcrypted data.
+/// * `data` - A slice of bytes with the 96bits nonce + the emcrypted data.
 /// * `key` - The decryption key as a 32-byte array.
 ///
 /// # Returns
 ///
-/// A vector of bytes containing the decrypted data.
+/// A vector of bytes containing the decrypted data, wiped from memory when dropped.
 ///
 /// # Errors
 ///
-/// This function will panic if the decryption operation fails.
-pub fn decrypt_data(data: Vec<u8>, key: &[u8]) -> Result<Vec<u8>, Error> {
+/// Returns `RauthyError::CorruptVault` if the data is too small to hold the nonce and
+/// `RauthyError::WrongPassword` if it can't be decrypted with the key.
+pub fn decrypt_data(data: &[u8], key: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
     // println!("Data to decrypt: {:?}, Len: {:?}", data, data.len());
-    let data = data.split_at(12);
+    if data.len() < NONCE_LEN {
+        return Err(RauthyError::CorruptVault);
+    }
+    let data = data.split_at(NONCE_LEN);
     let nonce = Nonce::from_slice(data.0);
     let encrypted_data = data.1; // Split the Nonce and encrypted data for GCM
                                  // println!("Data to decrypt: {:?}, Len: {:?}", encrypted_data, encrypted_data.len());
@@ -116,8 +180,8 @@ pub fn decrypt_data(data: Vec<u8>, key: &[u8]) -> Result<Vec<u8>, Error> {
 
     match cipher.decrypt(&nonce, encrypted_data) {
         // Decrypt the data using GCM
-        Ok(result) => Ok(result),
-        Err(_) => Err("Couldn't decrypt the text"),
+        Ok(result) => Ok(Zeroizing::new(result)),
+        Err(_) => Err(RauthyError::WrongPassword),
     }
 }
 
@@ -133,6 +197,25 @@ mod tests {
         assert_eq!(key.len(), CREDENTIAL_LEN);
     }
 
+    #[test]
+    fn test_derive_key_from_password_and_key_file() {
+        let salt = generate_salt();
+        let key_file = hash_key_file(b"key file content");
+        let key = derive_key_from_password_and_key_file("test_password", &key_file, &salt).unwrap();
+
+        assert_ne!(*key, *derive_key_from_password_and_salt("test_password", Some(&salt)).unwrap());
+        let other_file = hash_key_file(b"other content");
+        assert_ne!(*key, *derive_key_from_password_and_key_file("test_password", &other_file, &salt).unwrap());
+    }
+
+    #[test]
+    fn test_derive_key_from_secret() {
+        let salt = generate_salt();
+        let key = derive_key_from_secret_and_salt(&[7; 32], &salt).unwrap();
+        assert_eq!(*key, *derive_key_from_secret_and_salt(&[7; 32], &salt).unwrap());
+        assert_ne!(*key, *derive_key_from_secret_and_salt(&[7; 32], &generate_salt()).unwrap());
+    }
+
     #[test]
     fn test_encrypt_decrypt_data() {
         let data = b"Hello, world!".to_vec();
@@ -140,10 +223,20 @@ mod tests {
         let salt = generate_salt();
         let key = derive_key_from_password_and_salt(user_pass, Some(&salt)).unwrap();
 
-        let encrypted_data = encrypt_data(data.clone(), &key).unwrap();
-        let decrypted_data = decrypt_data(encrypted_data.clone(), &key).unwrap();
+        let encrypted_data = encrypt_data(&data, key.as_ref()).unwrap();
+        let decrypted_data = decrypt_data(&encrypted_data, key.as_ref()).unwrap();
+
+        assert_eq!(data, *decrypted_data);
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_key() {
+        let key = derive_key_from_password_and_salt("test_password", Some(&generate_salt())).unwrap();
+        let wrong_key = derive_key_from_password_and_salt("wrong_password", Some(&generate_salt())).unwrap();
 
-        assert_eq!(data, decrypted_data);
+        let encrypted_data = encrypt_data(b"Hello, world!", key.as_ref()).unwrap();
+        assert_eq!(decrypt_data(&encrypted_data, wrong_key.as_ref()), Err(RauthyError::WrongPassword));
+        assert_eq!(decrypt_data(&[0; 4], key.as_ref()), Err(RauthyError::CorruptVault));
     }
 
     #[test]
@@ -153,7 +246,7 @@ mod tests {
         let salt = generate_salt();
         let key = derive_key_from_password_and_salt(user_pass, Some(&salt)).unwrap();
 
-        let encrypted_data = encrypt_data(data.clone(), &key).unwrap();
+        let encrypted_data = encrypt_data(&data, key.as_ref()).unwrap();
         assert!(encrypted_data.len() > data.len()); // Encrypted data should be longer than original
     }
 }
@@ -0,0 +1,166 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+use std::io::Error as IoError;
+
+/// Error returned by the storage, crypto and brandfetch modules and by every command.
+///
+/// It's serialized to the frontend as `{ "code": "WRONG_PASSWORD", "message": "..." }`.
+/// The codes are stable, so the frontend can use them as translation keys. The message
+/// is only a fallback in english (and it's what we print in the logs).
+#[derive(Debug, Clone, PartialEq)]
+pub enum RauthyError {
+    /// The password can't decrypt the vault
+    WrongPassword,
+    /// The operation exposes secrets, so the password must be typed again
+    PasswordRequired,
+    /// The vault requires a key file besides the password
+    KeyFileRequired,
+    /// The duress password can't be the same as the password
+    SamePassword,
+    /// The name of a vault is empty or already used by another vault
+    InvalidVaultName(&'static str),
+    /// The vault is shared with a team, and this device has no identity to open it
+    IdentityRequired,
+    /// The member of a team vault can't be added or removed, or isn't a member
+    InvalidMember(&'static str),
+    /// The storage file changed on another device, but can't be merged with the open one
+    SyncConflict,
+    /// The recovery key is malformed or can't unlock the vault
+    InvalidRecoveryKey,
+    /// The shares of the recovery key are malformed or can't be combined
+    InvalidShares(&'static str),
+    /// Too many failed unlock attempts. Holds the seconds to wait before trying again
+    TooManyAttempts(u64),
+    /// The vault was deleted after too many failed unlock attempts
+    VaultWiped,
+    /// The vault was decrypted, but its content is truncated or can't be decoded
+    CorruptVault,
+    /// There is no open vault (no key to read or save the services)
+    VaultLocked,
+    /// Reading or writing a file failed
+    Io(String),
+    /// The text isn't a valid `otpauth://totp/` URI
+    InvalidUri,
+    /// The service secret or parameters can't be used to generate tokens
+    InvalidSecret(&'static str),
+    /// The token couldn't be generated with the current system time
+    TokenGeneration,
+    /// There is already a service with the same id
+    Duplicate,
+    /// There is no service with the provided id
+    NotFound,
+    /// The vault has no services for the requested operation
+    NoServices,
+    /// The CSV content couldn't be imported. Holds the errors of each line
+    InvalidCsv(String),
+    /// Encryption or key derivation failed
+    Crypto(&'static str),
+    /// A request to an external service failed
+    Network(String),
+    /// The transfer to or from another device failed or was cancelled
+    Transfer(&'static str),
+    /// The audit log can't be read or written
+    AuditLog(&'static str),
+    /// The biometric authentication failed or was cancelled
+    Biometric,
+}
+
+impl RauthyError {
+    /// Stable code of the error, sent to the frontend
+    pub fn code(&self) -> &'static str {
+        match self {
+            RauthyError::WrongPassword => "WRONG_PASSWORD",
+            RauthyError::PasswordRequired => "PASSWORD_REQUIRED",
+            RauthyError::KeyFileRequired => "KEY_FILE_REQUIRED",
+            RauthyError::SamePassword => "SAME_PASSWORD",
+            RauthyError::InvalidVaultName(_) => "INVALID_VAULT_NAME",
+            RauthyError::IdentityRequired => "IDENTITY_REQUIRED",
+            RauthyError::InvalidMember(_) => "INVALID_MEMBER",
+            RauthyError::SyncConflict => "SYNC_CONFLICT",
+            RauthyError::InvalidRecoveryKey => "INVALID_RECOVERY_KEY",
+            RauthyError::InvalidShares(_) => "INVALID_SHARES",
+            RauthyError::TooManyAttempts(_) => "TOO_MANY_ATTEMPTS",
+            RauthyError::VaultWiped => "VAULT_WIPED",
+            RauthyError::CorruptVault => "CORRUPT_VAULT",
+            RauthyError::VaultLocked => "VAULT_LOCKED",
+            RauthyError::Io(_) => "IO",
+            RauthyError::InvalidUri => "INVALID_URI",
+            RauthyError::InvalidSecret(_) => "INVALID_SECRET",
+            RauthyError::TokenGeneration => "TOKEN_GENERATION",
+            RauthyError::Duplicate => "DUPLICATE",
+            RauthyError::NotFound => "NOT_FOUND",
+            RauthyError::NoServices => "NO_SERVICES",
+            RauthyError::InvalidCsv(_) => "INVALID_CSV",
+            RauthyError::Crypto(_) => "CRYPTO",
+            RauthyError::Network(_) => "NETWORK",
+            RauthyError::Transfer(_) => "TRANSFER_FAILED",
+            RauthyError::AuditLog(_) => "AUDIT_LOG",
+            RauthyError::Biometric => "BIOMETRIC",
+        }
+    }
+}
+
+impl fmt::Display for RauthyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RauthyError::WrongPassword => write!(f, "Couldn't decrypt the storage file with this password"),
+            RauthyError::PasswordRequired => write!(f, "The password is required for this operation"),
+            RauthyError::KeyFileRequired => write!(f, "This storage file also requires its key file"),
+            RauthyError::SamePassword => write!(f, "The duress password must be different from the password"),
+            RauthyError::InvalidVaultName(err) => write!(f, "{}", err),
+            RauthyError::IdentityRequired => write!(f, "This vault is shared with a team: it's opened with your identity"),
+            RauthyError::InvalidMember(err) => write!(f, "{}", err),
+            RauthyError::SyncConflict => write!(f, "The storage file was replaced by a different one on another device"),
+            RauthyError::InvalidRecoveryKey => write!(f, "Couldn't unlock the storage file with this recovery key"),
+            RauthyError::InvalidShares(err) => write!(f, "{}", err),
+            RauthyError::TooManyAttempts(seconds) => write!(f, "Too many failed attempts, try again in {} seconds", seconds),
+            RauthyError::VaultWiped => write!(f, "The storage file was deleted after too many failed attempts"),
+            RauthyError::CorruptVault => write!(f, "The storage file is corrupted"),
+            RauthyError::VaultLocked => write!(f, "The storage file isn't open"),
+            RauthyError::Io(err) => write!(f, "File error: {}", err),
+            RauthyError::InvalidUri => write!(f, "Couldn't parse the provided URL as a TOTP URL"),
+            RauthyError::InvalidSecret(err) => write!(f, "{}", err),
+            RauthyError::TokenGeneration => write!(f, "Couldn't generate a token based on the current time"),
+            RauthyError::Duplicate => write!(f, "This service was already added"),
+            RauthyError::NotFound => write!(f, "Service not found"),
+            RauthyError::NoServices => write!(f, "There are no services"),
+            RauthyError::InvalidCsv(err) => write!(f, "Invalid CSV: {}", err),
+            RauthyError::Crypto(err) => write!(f, "{}", err),
+            RauthyError::Network(err) => write!(f, "Network error: {}", err),
+            RauthyError::Transfer(err) => write!(f, "{}", err),
+            RauthyError::AuditLog(err) => write!(f, "{}", err),
+            RauthyError::Biometric => write!(f, "Can't load biometric decrypted data"),
+        }
+    }
+}
+
+impl std::error::Error for RauthyError {}
+
+impl From<IoError> for RauthyError {
+    fn from(err: IoError) -> Self {
+        RauthyError::Io(err.to_string())
+    }
+}
+
+impl Serialize for RauthyError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("RauthyError", 2)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_error() {
+        let json = serde_json::to_string(&RauthyError::WrongPassword).unwrap();
+        assert_eq!(
+            json,
+            r#"{"code":"WRONG_PASSWORD","message":"Couldn't decrypt the storage file with this password"}"#
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::storage::{Service, ServiceView};
+
+/// Previous versions kept for each service
+const MAX_VERSIONS: usize = 10;
+/// Deleted services kept to be restored
+const MAX_DELETED: usize = 20;
+
+/// A service as it was before it was replaced or deleted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceVersion {
+    pub service: Service,
+    /// When it was replaced or deleted (unix seconds)
+    pub replaced: u64,
+}
+
+/// What the frontend gets: the version without its secret
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceVersionView {
+    pub service: ServiceView,
+    pub replaced: u64,
+}
+
+impl From<&ServiceVersion> for ServiceVersionView {
+    fn from(version: &ServiceVersion) -> Self {
+        Self {
+            service: ServiceView::from(&version.service),
+            replaced: version.replaced,
+        }
+    }
+}
+
+/// The previous versions of the services and the ones deleted recently, oldest first, so
+/// a secret overwritten while re-enrolling can be restored. They're kept in the encrypted
+/// payload, as they hold secrets. They aren't merged with the changes of other devices:
+/// each device keeps the history of the changes made on it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServiceHistory {
+    versions: HashMap<String, Vec<ServiceVersion>>,
+    deleted: Vec<ServiceVersion>,
+}
+
+impl ServiceHistory {
+    /// Keeps `previous`, replaced by `current`, unless they have the same secret, parameters
+    /// and label
+    pub fn record_change(&mut self, previous: Service, current: &Service, now: u64) {
+        if same_version(&previous, current) {
+            return;
+        }
+        let versions = self.versions.entry(previous.id.clone()).or_default();
+        versions.push(ServiceVersion { service: previous, replaced: now });
+        if versions.len() > MAX_VERSIONS {
+            versions.remove(0);
+        }
+    }
+
+    pub fn record_delete(&mut self, service: Service, now: u64) {
+        self.deleted.push(ServiceVersion { service, replaced: now });
+        if self.deleted.len() > MAX_DELETED {
+            self.deleted.remove(0);
+        }
+    }
+
+    pub fn versions(&self, id: &str) -> &[ServiceVersion] {
+        self.versions.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The version at `index` of [`ServiceHistory::versions`]
+    pub fn version(&self, id: &str, index: usize) -> Option<&Service> {
+        self.versions(id).get(index).map(|version| &version.service)
+    }
+
+    pub fn deleted(&self) -> &[ServiceVersion] {
+        &self.deleted
+    }
+
+    /// Removes the last deleted service with `id`, to restore it
+    pub fn take_deleted(&mut self, id: &str) -> Option<Service> {
+        let position = self.deleted.iter().rposition(|version| version.service.id == id)?;
+        Some(self.deleted.remove(position).service)
+    }
+}
+
+fn same_version(previous: &Service, current: &Service) -> bool {
+    previous.secret == current.secret
+        && previous.issuer == current.issuer
+        && previous.name == current.name
+        && previous.algorithm == current.algorithm
+        && previous.digits == current.digits
+        && previous.period == current.period
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zeroize::Zeroizing;
+
+    fn service(secret: &str) -> Service {
+        Service {
+            id: String::from("github"),
+            issuer: String::from("GitHub"),
+            secret: Zeroizing::new(secret.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_change() {
+        let mut history = ServiceHistory::default();
+        let mut renamed = service("JBSWY3DPEHPK3PXP");
+        renamed.icon = String::from("https://github.com/favicon.ico");
+        // Only the icon changed
+        history.record_change(service("JBSWY3DPEHPK3PXP"), &renamed, 10);
+        assert!(history.versions("github").is_empty());
+
+        for (replaced, secret) in (0..MAX_VERSIONS as u64 + 2).zip(["A", "B"].iter().cycle()) {
+            history.record_change(service(secret), &service("NEW"), replaced);
+        }
+        let versions = history.versions("github");
+        assert_eq!(versions.len(), MAX_VERSIONS);
+        assert_eq!(versions[0].replaced, 2);
+        assert_eq!(history.version("github", 1).unwrap().secret.as_str(), "B");
+        assert!(history.version("github", MAX_VERSIONS).is_none());
+    }
+
+    #[test]
+    fn test_take_deleted() {
+        let mut history = ServiceHistory::default();
+        history.record_delete(service("OLD"), 10);
+        history.record_delete(service("NEWER"), 20);
+
+        assert_eq!(history.take_deleted("github").unwrap().secret.as_str(), "NEWER");
+        assert_eq!(history.take_deleted("github").unwrap().secret.as_str(), "OLD");
+        assert!(history.take_deleted("github").is_none());
+    }
+}
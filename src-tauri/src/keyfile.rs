@@ -0,0 +1,44 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, KeyArray};
+use crate::error::RauthyError;
+
+/// Reads a key file and returns its hash. The file can be anything (a photo, a document...),
+/// as long as it never changes.
+pub fn read_key_file(path: &Path) -> Result<Zeroizing<KeyArray>, RauthyError> {
+    let mut content = Zeroizing::new(Vec::new());
+    File::open(path)?.read_to_end(&mut content)?;
+    if content.is_empty() {
+        return Err(RauthyError::Crypto("The key file is empty"));
+    }
+    Ok(crypto::hash_key_file(&content))
+}
+
+/// Writes a new key file with 256 random bits, hex encoded. It never overwrites an existing file.
+pub fn generate_key_file(path: &Path) -> Result<(), RauthyError> {
+    let key = crypto::generate_key();
+    let content = Zeroizing::new(hex::encode(key.as_ref()));
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_read_key_file() {
+        let path = std::env::temp_dir().join(format!("rauthy-test-{}.key", rand::random::<u32>()));
+        generate_key_file(&path).unwrap();
+        let hash = read_key_file(&path).unwrap();
+        assert_eq!(*hash, *read_key_file(&path).unwrap());
+
+        // Never overwrites
+        assert!(generate_key_file(&path).is_err());
+        std::fs::remove_file(path).ok();
+    }
+}
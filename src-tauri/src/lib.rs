@@ -2,15 +2,33 @@ use std::sync::Mutex;
 use tauri::Manager;
 use tauri_plugin_fs::FsExt;
 
+mod audit;
+mod autolock;
 mod biometric;
 mod brandfetch;
 mod commands;
 mod crypto;
 #[cfg(desktop)]
 mod desktop;
+pub mod error;
+mod history;
+mod keyfile;
+mod lockout;
+mod organize;
+mod recovery;
+mod search;
+mod settings;
+mod shamir;
 mod state;
-mod storage;
-mod totp;
+pub mod storage;
+mod sync;
+mod team;
+mod ticker;
+mod transfer;
+pub mod totp;
+mod vault;
+mod vaults;
+mod webdav;
 
 #[cfg(mobile)]
 const IS_MOBILE: bool = true;
@@ -28,17 +46,26 @@ pub fn run() {
         }
         
         app.manage(Mutex::new(state::AppState::default()));
-        let path = app
-            .path()
-            .app_local_data_dir()
-            .expect("Couldn't resolve app local data dir"); //.join("Rauthy.bin");
+        // The app local data dir, or the dir of the executable in portable mode
+        let path = vaults::data_dir(app.handle());
         let scope = app.fs_scope();
-        // scope.allow_file(path.clone()).unwrap();
         dbg!(scope.is_allowed(path.clone()));
 
         let app_state = app.state::<Mutex<state::AppState>>();
         let mut state = app_state.lock().unwrap();
+        state.settings = settings::Settings::load(&path);
+        state.vaults = vaults::VaultRegistry::load(&path);
         state.storage_path = path;
+        drop(state);
+
+        let token_ticker = ticker::spawn_token_ticker(app.handle().clone());
+        app.manage(token_ticker);
+
+        let idle_timer = autolock::spawn_idle_timer(app.handle().clone());
+        app.manage(idle_timer);
+        app.manage(transfer::PendingTransfer::default());
+        #[cfg(target_os = "linux")]
+        autolock::spawn_logind_listener(app.handle().clone());
 
         #[cfg(debug_assertions)] // only include this code on debug builds
         {
@@ -64,14 +91,68 @@ pub fn run() {
             commands::setup_storage_keys,
             commands::get_services_tokens,
             commands::update_service,
+            commands::reveal_secret,
             commands::delete_service,
+            commands::get_service_history,
+            commands::restore_service_version,
+            commands::list_deleted_services,
+            commands::undo_delete_service,
+            commands::filter_services,
+            commands::list_service_groups,
+            commands::tag_services,
+            commands::move_services_to_folder,
+            commands::search_services,
+            commands::list_service_order,
+            commands::set_sort_mode,
+            commands::move_service,
+            commands::reorder_services,
+            commands::record_service_use,
+            commands::list_stale_services,
             commands::get_service_icon,
             commands::export_services_csv,
             commands::import_services_csv,
             commands::change_password,
+            commands::unlock_with_recovery_key,
+            commands::take_recovery_key,
+            commands::regenerate_recovery_key,
+            commands::split_recovery_key,
+            commands::unlock_with_recovery_shares,
+            commands::generate_key_file,
+            commands::set_duress_password,
+            commands::remove_duress_password,
+            commands::list_vaults,
+            commands::create_vault,
+            commands::open_vault,
+            commands::rename_vault,
+            commands::add_vault_file,
+            commands::move_vault,
+            commands::delete_vault,
+            commands::sync_vault,
+            commands::set_webdav_sync,
+            commands::disable_webdav_sync,
+            commands::get_webdav_sync,
+            commands::download_webdav_vault,
+            commands::start_transfer,
+            commands::confirm_transfer,
+            commands::receive_transfer,
+            commands::create_identity,
+            commands::get_identity,
+            commands::create_team_vault,
+            commands::list_members,
+            commands::add_member,
+            commands::remove_member,
+            commands::get_audit_log,
+            commands::export_audit_log,
             commands::close_services_file,
+            commands::report_activity,
+            commands::get_settings,
+            commands::update_settings,
             #[cfg(mobile)]
             commands::fetch_without_pass,
+            #[cfg(mobile)]
+            commands::enable_biometric_unlock,
+            #[cfg(mobile)]
+            commands::disable_biometric_unlock,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
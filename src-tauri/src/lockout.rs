@@ -0,0 +1,259 @@
+use ring::{digest, hmac};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Runtime};
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, KeyArray};
+use crate::error::RauthyError;
+
+/// Event emitted when an unlock attempt fails or is refused, with how long the UI must wait
+pub const UNLOCK_THROTTLED_EVENT: &str = "unlock-throttled";
+
+const ATTEMPTS_EXTENSION: &str = "attempts";
+/// The key of the counter files of this device, in the data dir
+const DEVICE_KEY_FILE: &str = "attempts.key";
+/// Failures allowed before we start asking the user to wait
+const FREE_ATTEMPTS: u32 = 3;
+/// Longest wait between two attempts (1 hour)
+const MAX_BACKOFF_SECONDS: u64 = 60 * 60;
+/// Failures added to the wait when the counter file was tampered with or can't be read.
+/// They don't count towards the wipe policy, as they may not be failures at all.
+const TAMPERED_FAILURES: u32 = 10;
+const MAC_CONTEXT: &[u8] = b"rauthy-failed-attempts";
+/// count (u32) + penalty (u32) + last_failure (u64) + HMAC-SHA256
+const FILE_LEN: usize = 4 + 4 + 8 + 32;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ThrottledPayload {
+    failed_attempts: u32,
+    wait_seconds: u64,
+}
+
+/// Failed unlock attempts, persisted next to the vault so they survive restarts.
+///
+/// The file is authenticated with an HMAC keyed by a secret of this device (see
+/// [`device_key`]), so it can't be edited to reset the counter, nor copied from another
+/// device. The key isn't in the vault file, so a vault synced from another device with a
+/// new password keeps its counter. Anyone that can delete the file can also copy
+/// `Rauthy.bin` and attack it offline, so this only protects the unlock screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FailedAttempts {
+    /// Wrong passwords typed, which count towards the wipe policy
+    pub count: u32,
+    /// Failures assumed because the file was tampered with, which only make the user wait
+    pub penalty: u32,
+    /// Unix timestamp (in seconds) of the last failure
+    pub last_failure: u64,
+}
+
+impl FailedAttempts {
+    /// The counter file of the vault at `vault_path` (e.g. `Rauthy.attempts`)
+    pub fn path(vault_path: &Path) -> PathBuf {
+        vault_path.with_extension(ATTEMPTS_EXTENSION)
+    }
+
+    /// Loads the counter. A missing file means no failures. A file that doesn't match
+    /// its HMAC adds a penalty of [`TAMPERED_FAILURES`] failures starting now, and is
+    /// rewritten so the wait can expire.
+    pub fn load(path: &Path, key: &KeyArray) -> Self {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(_) => return Self::default(),
+        };
+
+        if content.len() == FILE_LEN {
+            let (data, tag) = content.split_at(FILE_LEN - 32);
+            if hmac::verify(&mac_key(key), data, tag).is_ok() {
+                return Self {
+                    count: u32::from_le_bytes(data[..4].try_into().unwrap()),
+                    penalty: u32::from_le_bytes(data[4..8].try_into().unwrap()),
+                    last_failure: u64::from_le_bytes(data[8..].try_into().unwrap()),
+                };
+            }
+        }
+
+        eprintln!("The failed attempts file was tampered with");
+        let attempts = Self {
+            count: 0,
+            penalty: TAMPERED_FAILURES,
+            last_failure: unix_now(),
+        };
+        attempts.save(path, key).ok();
+        attempts
+    }
+
+    pub fn save(&self, path: &Path, key: &KeyArray) -> Result<(), RauthyError> {
+        let mut content = Vec::with_capacity(FILE_LEN);
+        content.extend_from_slice(&self.count.to_le_bytes());
+        content.extend_from_slice(&self.penalty.to_le_bytes());
+        content.extend_from_slice(&self.last_failure.to_le_bytes());
+        let tag = hmac::sign(&mac_key(key), &content);
+        content.extend_from_slice(tag.as_ref());
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Clears the counter after a successful unlock
+    pub fn reset(path: &Path, key: &KeyArray) -> Result<(), RauthyError> {
+        Self::default().save(path, key)
+    }
+
+    pub fn record_failure(&mut self, now: u64) {
+        self.count = self.count.saturating_add(1);
+        self.last_failure = now;
+    }
+
+    /// Seconds the user still has to wait before trying again
+    pub fn wait_seconds(&self, now: u64) -> u64 {
+        // If the clock went backwards, we count from now
+        let elapsed = now.saturating_sub(self.last_failure);
+        backoff_seconds(self.count.saturating_add(self.penalty)).saturating_sub(elapsed)
+    }
+}
+
+/// The key of the failed attempts files of this device, created the first time in `dir`.
+/// It's never synced, unlike the vault.
+pub fn device_key(dir: &Path) -> Result<Zeroizing<KeyArray>, RauthyError> {
+    let path = dir.join(DEVICE_KEY_FILE);
+    if let Ok(content) = fs::read(&path) {
+        let content = Zeroizing::new(content);
+        if let Ok(key) = KeyArray::try_from(content.as_slice()) {
+            return Ok(Zeroizing::new(key));
+        }
+    }
+
+    // Counters authenticated with a lost key are treated as tampered with
+    let key = crypto::generate_key();
+    fs::create_dir_all(dir)?;
+    fs::write(&path, key.as_ref())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?; // Read/write for owner only
+    }
+    Ok(key)
+}
+
+/// Wait required after `failures` failed attempts: nothing for the first [`FREE_ATTEMPTS`],
+/// then 1s, 2s, 4s... up to [`MAX_BACKOFF_SECONDS`]
+pub fn backoff_seconds(failures: u32) -> u64 {
+    if failures < FREE_ATTEMPTS {
+        return 0;
+    }
+    let exponent = failures - FREE_ATTEMPTS;
+    if exponent >= 63 {
+        return MAX_BACKOFF_SECONDS;
+    }
+    (1u64 << exponent).min(MAX_BACKOFF_SECONDS)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn emit_throttled<R: Runtime>(app: &AppHandle<R>, attempts: &FailedAttempts, wait_seconds: u64) {
+    let payload = ThrottledPayload {
+        failed_attempts: attempts.count,
+        wait_seconds,
+    };
+    if let Err(err) = app.emit(UNLOCK_THROTTLED_EVENT, payload) {
+        eprintln!("Couldn't emit the unlock-throttled event: {err}");
+    }
+}
+
+fn mac_key(secret: &[u8]) -> hmac::Key {
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(MAC_CONTEXT);
+    context.update(secret);
+    hmac::Key::new(hmac::HMAC_SHA256, context.finish().as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempts_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rauthy-test-{}.attempts", name))
+    }
+
+    #[test]
+    fn test_backoff_seconds() {
+        assert_eq!(backoff_seconds(0), 0);
+        assert_eq!(backoff_seconds(FREE_ATTEMPTS - 1), 0);
+        assert_eq!(backoff_seconds(FREE_ATTEMPTS), 1);
+        assert_eq!(backoff_seconds(FREE_ATTEMPTS + 3), 8);
+        assert_eq!(backoff_seconds(u32::MAX), MAX_BACKOFF_SECONDS);
+    }
+
+    #[test]
+    fn test_wait_seconds() {
+        let mut attempts = FailedAttempts::default();
+        for _ in 0..FREE_ATTEMPTS + 2 {
+            attempts.record_failure(1000);
+        }
+        assert_eq!(attempts.wait_seconds(1000), 4);
+        assert_eq!(attempts.wait_seconds(1003), 1);
+        assert_eq!(attempts.wait_seconds(1004), 0);
+        // Clock went backwards
+        assert_eq!(attempts.wait_seconds(10), 4);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = attempts_path("save-load");
+        let key = [1u8; 32];
+        let attempts = FailedAttempts {
+            count: 5,
+            penalty: 0,
+            last_failure: 1234,
+        };
+        attempts.save(&path, &key).unwrap();
+        assert_eq!(FailedAttempts::load(&path, &key), attempts);
+
+        FailedAttempts::reset(&path, &key).unwrap();
+        assert_eq!(FailedAttempts::load(&path, &key), FailedAttempts::default());
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_tampered_file() {
+        let path = attempts_path("tampered");
+        let key = [2u8; 32];
+        let attempts = FailedAttempts {
+            count: 5,
+            penalty: 0,
+            last_failure: 1234,
+        };
+        attempts.save(&path, &key).unwrap();
+
+        // Resetting the counter without the HMAC key
+        let mut content = fs::read(&path).unwrap();
+        content[..4].copy_from_slice(&0u32.to_le_bytes());
+        fs::write(&path, content).unwrap();
+        let tampered = FailedAttempts::load(&path, &key);
+        assert_eq!(tampered.penalty, TAMPERED_FAILURES);
+        // The penalty makes the user wait, but isn't counted towards the wipe policy
+        assert_eq!(tampered.count, 0);
+        assert_eq!(tampered.wait_seconds(tampered.last_failure), backoff_seconds(TAMPERED_FAILURES));
+
+        // Copying the file from another device
+        attempts.save(&path, &[3u8; 32]).unwrap();
+        assert_eq!(FailedAttempts::load(&path, &key).penalty, TAMPERED_FAILURES);
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_device_key() {
+        let dir = std::env::temp_dir().join(format!("rauthy-test-device-{}", rand::random::<u32>()));
+        let key = device_key(&dir).unwrap();
+        assert_eq!(*key, *device_key(&dir).unwrap());
+        fs::remove_dir_all(dir).ok();
+    }
+}
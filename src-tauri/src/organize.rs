@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use crate::storage::Service;
+
+/// Separates the tags in a single field, e.g. in CSV files
+pub const TAG_SEPARATOR: char = ';';
+
+const DAY_SECONDS: u64 = 24 * 60 * 60;
+/// Months in the stale services report are counted as 30 days
+const MONTH_DAYS: u64 = 30;
+
+/// How the services are listed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortMode {
+    /// The order the user arranged them in
+    #[default]
+    Manual,
+    Alphabetical,
+    MostUsed,
+    RecentlyAdded,
+    RecentlyUsed,
+}
+
+/// What the frontend gets: the ids of the services in the order they're listed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceOrder {
+    pub sort: SortMode,
+    pub ids: Vec<String>,
+}
+
+/// Which services to list. Empty fields match every service
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceFilter {
+    /// Services with all of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub favorites_only: bool,
+    /// Services used in the last days
+    pub used_within_days: Option<u64>,
+}
+
+/// A service that wasn't used for months, e.g. an account that was closed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StaleService {
+    pub id: String,
+    pub label: String,
+    /// When its token was last copied or its secret revealed (unix seconds), `0` if never
+    pub last_used: u64,
+    pub uses: u64,
+    pub created: u64,
+}
+
+/// The tags and folders used by the services of the vault, sorted
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceGroups {
+    pub tags: Vec<String>,
+    pub folders: Vec<String>,
+}
+
+impl ServiceFilter {
+    /// Checks if the service matches, `now` being the current unix time
+    pub fn matches(&self, service: &Service, now: u64) -> bool {
+        (!self.favorites_only || service.favorite)
+            && self.used_within_days.is_none_or(|days| {
+                service.last_used > 0 && service.last_used >= now.saturating_sub(days * DAY_SECONDS)
+            })
+            && self.tags.iter().all(|tag| service.has_tag(tag))
+            && self.folder.as_deref().and_then(normalize_folder).is_none_or(|folder| {
+                service.folder.as_deref().is_some_and(|current| current.eq_ignore_ascii_case(&folder))
+            })
+    }
+}
+
+impl ServiceGroups {
+    pub fn of<'a>(services: impl IntoIterator<Item = &'a Service>) -> Self {
+        let mut tags = Vec::new();
+        let mut folders = Vec::new();
+        for service in services {
+            tags.extend(service.tags.iter().cloned());
+            folders.extend(service.folder.iter().cloned());
+        }
+        Self {
+            tags: normalize_tags(tags),
+            folders: normalize_tags(folders),
+        }
+    }
+}
+
+/// Sorts the services by `mode`. Ties (e.g. the services of older versions, all at
+/// position `0`) are sorted alphabetically and then by id, so the order is the same on
+/// every call.
+pub fn sort_services<'a>(services: impl IntoIterator<Item = &'a Service>, mode: SortMode) -> Vec<&'a Service> {
+    let mut sorted: Vec<&Service> = services.into_iter().collect();
+    sorted.sort_by(|a, b| {
+        let order = match mode {
+            SortMode::Manual => a.position.cmp(&b.position),
+            SortMode::Alphabetical => Ordering::Equal,
+            SortMode::MostUsed => b.uses.cmp(&a.uses),
+            SortMode::RecentlyAdded => b.created.cmp(&a.created),
+            SortMode::RecentlyUsed => b.last_used.cmp(&a.last_used),
+        };
+        order.then_with(|| alphabetical(a, b))
+    });
+    sorted
+}
+
+/// The services not used for `months`, the ones unused for longest first. Services added
+/// in that time aren't stale yet. Services saved by older versions are left out until
+/// they're used, as it isn't known when they were added.
+pub fn stale_services<'a>(services: impl IntoIterator<Item = &'a Service>, months: u64, now: u64) -> Vec<StaleService> {
+    let since = now.saturating_sub(months * MONTH_DAYS * DAY_SECONDS);
+    let active = |service: &Service| service.last_used.max(service.created);
+    let mut stale: Vec<&Service> = services
+        .into_iter()
+        .filter(|service| active(service) > 0 && active(service) < since)
+        .collect();
+    stale.sort_by(|a, b| active(a).cmp(&active(b)).then_with(|| alphabetical(a, b)));
+    stale
+        .into_iter()
+        .map(|service| StaleService {
+            id: service.id.clone(),
+            label: service.label(),
+            last_used: service.last_used,
+            uses: service.uses,
+            created: service.created,
+        })
+        .collect()
+}
+
+/// By issuer, then by account name, ignoring case, and then by id
+pub fn alphabetical(a: &Service, b: &Service) -> Ordering {
+    a.issuer
+        .to_lowercase()
+        .cmp(&b.issuer.to_lowercase())
+        .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+/// Trims the tags (or folders) and drops the empty ones and the repeated ones (ignoring
+/// case), keeping the first spelling, sorted
+pub fn normalize_tags(tags: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|known| known.eq_ignore_ascii_case(tag)) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized.sort_by_key(|tag| tag.to_lowercase());
+    normalized
+}
+
+/// Parses tags exported as a single field, e.g. `work;banking`
+pub fn parse_tags(field: &str) -> Vec<String> {
+    normalize_tags(field.split(TAG_SEPARATOR).map(String::from))
+}
+
+/// The folder, trimmed, or `None` for a blank name
+pub fn normalize_folder(folder: &str) -> Option<String> {
+    let folder = folder.trim();
+    (!folder.is_empty()).then(|| folder.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(tags: &[&str], folder: Option<&str>, favorite: bool) -> Service {
+        Service {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            folder: folder.map(String::from),
+            favorite,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = vec![" Work ", "banking", "", "work", "Admin"];
+        assert_eq!(normalize_tags(tags.into_iter().map(String::from)), vec!["Admin", "banking", "Work"]);
+        assert_eq!(parse_tags("work; banking;;"), vec!["banking", "work"]);
+        assert_eq!(normalize_folder("  "), None);
+    }
+
+    #[test]
+    fn test_filter() {
+        let work = service(&["work", "admin"], Some("Cloud"), true);
+        let personal = service(&["personal"], None, false);
+
+        let filter = ServiceFilter { tags: vec![String::from("WORK")], ..Default::default() };
+        assert!(filter.matches(&work, 0) && !filter.matches(&personal, 0));
+        let filter = ServiceFilter { folder: Some(String::from("cloud")), ..Default::default() };
+        assert!(filter.matches(&work, 0) && !filter.matches(&personal, 0));
+        let filter = ServiceFilter { favorites_only: true, ..Default::default() };
+        assert!(filter.matches(&work, 0) && !filter.matches(&personal, 0));
+        assert!(ServiceFilter::default().matches(&personal, 0));
+
+        let groups = ServiceGroups::of([&work, &personal]);
+        assert_eq!(groups.tags, vec!["admin", "personal", "work"]);
+        assert_eq!(groups.folders, vec!["Cloud"]);
+    }
+
+    #[test]
+    fn test_sort_services() {
+        let sorted_ids = |services: &[Service], mode| {
+            sort_services(services, mode).iter().map(|service| service.id.clone()).collect::<Vec<_>>()
+        };
+        let service = |id: &str, issuer: &str, position, uses, created, last_used| Service {
+            id: String::from(id),
+            issuer: String::from(issuer),
+            position,
+            uses,
+            created,
+            last_used,
+            ..Default::default()
+        };
+        let services = [
+            service("github", "GitHub", 2, 5, 30, 40),
+            service("aws", "aws", 0, 9, 10, 50),
+            service("gitlab", "GitLab", 0, 5, 20, 0),
+        ];
+
+        assert_eq!(sorted_ids(&services, SortMode::Manual), vec!["aws", "gitlab", "github"]);
+        assert_eq!(sorted_ids(&services, SortMode::Alphabetical), vec!["aws", "github", "gitlab"]);
+        assert_eq!(sorted_ids(&services, SortMode::MostUsed), vec!["aws", "github", "gitlab"]);
+        assert_eq!(sorted_ids(&services, SortMode::RecentlyAdded), vec!["github", "gitlab", "aws"]);
+        assert_eq!(sorted_ids(&services, SortMode::RecentlyUsed), vec!["aws", "github", "gitlab"]);
+    }
+
+    #[test]
+    fn test_usage() {
+        let now = 400 * DAY_SECONDS;
+        let service = |id: &str, created_day: u64, used_day: u64| Service {
+            id: String::from(id),
+            issuer: String::from(id),
+            created: created_day * DAY_SECONDS,
+            last_used: used_day * DAY_SECONDS,
+            ..Default::default()
+        };
+        let services = [
+            service("daily", 10, 399),
+            service("closed", 10, 100),
+            service("never-used", 20, 0),
+            service("new", 380, 0),
+            // Saved by an older version
+            service("unknown", 0, 0),
+        ];
+
+        let stale = stale_services(&services, 6, now);
+        assert_eq!(stale.iter().map(|stale| stale.id.as_str()).collect::<Vec<_>>(), vec!["never-used", "closed"]);
+        assert_eq!(stale[1].last_used, 100 * DAY_SECONDS);
+
+        let filter = ServiceFilter { used_within_days: Some(7), ..Default::default() };
+        let recent: Vec<_> = services.iter().filter(|service| filter.matches(service, now)).collect();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].id, "daily");
+    }
+}
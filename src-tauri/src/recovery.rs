@@ -0,0 +1,162 @@
+use data_encoding::BASE32_NOPAD;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand::RngCore;
+use ring::digest;
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use crate::error::RauthyError;
+use crate::shamir::Share;
+
+/// 256 bits, the same strength as the data key it protects
+const RECOVERY_KEY_LEN: usize = 32;
+/// Characters in each group of the printed key
+const GROUP_LEN: usize = 4;
+
+/// Generates a new recovery key. It's only shown to the user once, formatted by
+/// [`format_recovery_key`], so it can be written down or printed.
+pub fn generate_recovery_key() -> Zeroizing<Vec<u8>> {
+    let mut key = Zeroizing::new(vec![0u8; RECOVERY_KEY_LEN]);
+    rand::rng().fill_bytes(key.as_mut_slice());
+    key
+}
+
+/// Formats the key as groups of base32 characters, e.g. `ABCD-EFGH-...`
+pub fn format_recovery_key(key: &[u8]) -> Zeroizing<String> {
+    encode_groups(key)
+}
+
+/// Parses a key typed by the user. Case, spaces and dashes are ignored.
+pub fn parse_recovery_key(text: &str) -> Result<Zeroizing<Vec<u8>>, RauthyError> {
+    let key = decode_groups(text).ok_or(RauthyError::InvalidRecoveryKey)?;
+    if key.len() != RECOVERY_KEY_LEN {
+        return Err(RauthyError::InvalidRecoveryKey);
+    }
+    Ok(key)
+}
+
+/// A share of the recovery key, as sent to the frontend to be printed or saved
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryShare {
+    pub text: Zeroizing<String>,
+    /// SVG image of the QR code with the same text
+    pub qr_code: Zeroizing<String>,
+}
+
+impl TryFrom<&Share> for RecoveryShare {
+    type Error = RauthyError;
+
+    fn try_from(share: &Share) -> Result<Self, Self::Error> {
+        let text = format_share(share);
+        let qr_code = share_qr_code(&text)?;
+        Ok(Self { text, qr_code })
+    }
+}
+
+/// Formats a share of the recovery key as `threshold-index-` followed by the groups of
+/// its data and a checksum, so typos are caught before trying to unlock the vault.
+pub fn format_share(share: &Share) -> Zeroizing<String> {
+    let mut data = Zeroizing::new(share.data.to_vec());
+    data.extend_from_slice(&share_checksum(share.threshold, share.index, &share.data));
+    Zeroizing::new(format!("{}-{}-{}", share.threshold, share.index, encode_groups(&data).as_str()))
+}
+
+pub fn parse_share(text: &str) -> Result<Share, RauthyError> {
+    const INVALID: RauthyError = RauthyError::InvalidShares("The share is malformed");
+
+    let mut parts = text.trim().splitn(3, '-');
+    let threshold = parts.next().and_then(|part| part.trim().parse().ok()).ok_or(INVALID)?;
+    let index = parts.next().and_then(|part| part.trim().parse().ok()).ok_or(INVALID)?;
+    let mut data = decode_groups(parts.next().ok_or(INVALID)?).ok_or(INVALID)?;
+    if data.len() <= CHECKSUM_LEN {
+        return Err(INVALID);
+    }
+
+    let data_len = data.len() - CHECKSUM_LEN;
+    let checksum = data.split_off(data_len);
+    if checksum != share_checksum(threshold, index, &data) {
+        return Err(RauthyError::InvalidShares("The share has a typo"));
+    }
+    Ok(Share { threshold, index, data })
+}
+
+/// Renders the text of a share as a QR code, in SVG
+pub fn share_qr_code(text: &str) -> Result<Zeroizing<String>, RauthyError> {
+    let code = QrCode::new(text.as_bytes())
+        .map_err(|_| RauthyError::InvalidShares("The share is too big for a QR code"))?;
+    Ok(Zeroizing::new(
+        code.render::<svg::Color>().min_dimensions(256, 256).build(),
+    ))
+}
+
+const CHECKSUM_LEN: usize = 4;
+
+fn share_checksum(threshold: u8, index: u8, data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(&[threshold, index]);
+    context.update(data);
+    let mut checksum = [0u8; CHECKSUM_LEN];
+    checksum.copy_from_slice(&context.finish().as_ref()[..CHECKSUM_LEN]);
+    checksum
+}
+
+fn encode_groups(data: &[u8]) -> Zeroizing<String> {
+    let encoded = Zeroizing::new(BASE32_NOPAD.encode(data));
+    let groups: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(GROUP_LEN)
+        // Base32 is ASCII, so each chunk is valid UTF-8
+        .map(|group| std::str::from_utf8(group).unwrap())
+        .collect();
+    Zeroizing::new(groups.join("-"))
+}
+
+/// Decodes text typed by the user, ignoring case, spaces and dashes
+fn decode_groups(text: &str) -> Option<Zeroizing<Vec<u8>>> {
+    let normalized: Zeroizing<String> = Zeroizing::new(
+        text.chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| c.to_ascii_uppercase())
+            .collect(),
+    );
+    BASE32_NOPAD.decode(normalized.as_bytes()).ok().map(Zeroizing::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_and_parse_recovery_key() {
+        let key = generate_recovery_key();
+        let formatted = format_recovery_key(&key);
+        assert!(formatted.split('-').all(|group| group.len() <= GROUP_LEN));
+
+        assert_eq!(*parse_recovery_key(&formatted).unwrap(), *key);
+        let typed = formatted.to_lowercase().replace('-', " ");
+        assert_eq!(*parse_recovery_key(&typed).unwrap(), *key);
+    }
+
+    #[test]
+    fn test_format_and_parse_share() {
+        let shares = crate::shamir::split_secret(&generate_recovery_key(), 3, 2).unwrap();
+        let text = format_share(&shares[1]);
+        assert!(text.starts_with("2-2-"));
+        assert_eq!(parse_share(&text).unwrap(), shares[1]);
+
+        // A typo in the data
+        let mut typo = text.to_string();
+        let last = typo.pop().unwrap();
+        typo.push(if last == 'A' { 'B' } else { 'A' });
+        assert!(parse_share(&typo).is_err());
+        assert!(share_qr_code(&text).unwrap().starts_with("<?xml"));
+    }
+
+    #[test]
+    fn test_parse_invalid_recovery_key() {
+        assert_eq!(parse_recovery_key("ABCD-EFGH").unwrap_err(), RauthyError::InvalidRecoveryKey);
+        assert_eq!(parse_recovery_key("not a key!").unwrap_err(), RauthyError::InvalidRecoveryKey);
+    }
+}
@@ -0,0 +1,220 @@
+use serde::Serialize;
+
+use crate::organize;
+use crate::storage::Service;
+
+/// The field of a service a term of the query matched
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchField {
+    Issuer,
+    Name,
+    Tag,
+    Folder,
+}
+
+/// The characters of a field that matched the query, to highlight them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Highlight {
+    pub field: SearchField,
+    /// The text of the field, to tell the tags apart
+    pub value: String,
+    /// Ranges of matched characters (not bytes), as `[start, end)`, sorted
+    pub ranges: Vec<[usize; 2]>,
+}
+
+/// A service that matches the query, with how well it matched
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    pub id: String,
+    pub score: u32,
+    pub highlights: Vec<Highlight>,
+}
+
+/// How much a match in each field counts: the issuer is what people usually type
+fn weight(field: SearchField) -> u32 {
+    match field {
+        SearchField::Issuer => 3,
+        SearchField::Name | SearchField::Tag => 2,
+        SearchField::Folder => 1,
+    }
+}
+
+/// Finds the services that match every word of `query`, best matches first.
+///
+/// Each word matches a field when its characters appear in it in order (e.g. `gthb` matches
+/// `GitHub`), ignoring case. Words found as a whole, at the start of the field or of a word
+/// in it rank higher than scattered characters. Ties are sorted like [`SortMode::Alphabetical`].
+///
+/// [`SortMode::Alphabetical`]: crate::organize::SortMode::Alphabetical
+pub fn search_services<'a>(services: impl IntoIterator<Item = &'a Service>, query: &str) -> Vec<SearchMatch> {
+    let terms: Vec<Vec<char>> = query.split_whitespace().map(lowercase).collect();
+    if terms.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches: Vec<(&Service, SearchMatch)> = services
+        .into_iter()
+        .filter_map(|service| match_service(service, &terms).map(|found| (service, found)))
+        .collect();
+    matches.sort_by(|(a, found_a), (b, found_b)| {
+        found_b.score.cmp(&found_a.score).then_with(|| organize::alphabetical(a, b))
+    });
+    matches.into_iter().map(|(_, found)| found).collect()
+}
+
+fn match_service(service: &Service, terms: &[Vec<char>]) -> Option<SearchMatch> {
+    let mut fields = vec![(SearchField::Issuer, service.issuer.as_str()), (SearchField::Name, service.name.as_str())];
+    fields.extend(service.tags.iter().map(|tag| (SearchField::Tag, tag.as_str())));
+    fields.extend(service.folder.iter().map(|folder| (SearchField::Folder, folder.as_str())));
+
+    let mut score = 0;
+    let mut highlights: Vec<Highlight> = Vec::new();
+    for term in terms {
+        // The field where the term matched best
+        let (field, value, term_score, ranges) = fields
+            .iter()
+            .filter_map(|(field, value)| {
+                fuzzy_match(term, value).map(|(score, ranges)| (*field, *value, score * weight(*field), ranges))
+            })
+            .max_by_key(|(_, _, score, _)| *score)?;
+        score += term_score;
+        match highlights.iter_mut().find(|highlight| highlight.field == field && highlight.value == value) {
+            Some(highlight) => highlight.ranges = merge_ranges(&highlight.ranges, &ranges),
+            None => highlights.push(Highlight { field, value: value.to_string(), ranges }),
+        }
+    }
+
+    Some(SearchMatch { id: service.id.clone(), score, highlights })
+}
+
+/// Scores how `term` (lowercase) matches `value`, with the ranges of the matched characters
+fn fuzzy_match(term: &[char], value: &str) -> Option<(u32, Vec<[usize; 2]>)> {
+    let text = lowercase(value);
+    let len = term.len();
+    if len == 0 || len > text.len() {
+        return None;
+    }
+
+    // The term as a whole, preferably at the start of a word
+    let starts = (0..=text.len() - len).filter(|start| text[*start..*start + len] == *term);
+    if let Some(start) = starts.min_by_key(|start| (!is_word_start(&text, *start), *start)) {
+        let mut score = 100 + 10 * len as u32;
+        if len == text.len() {
+            score += 100;
+        } else if start == 0 {
+            score += 60;
+        } else if is_word_start(&text, start) {
+            score += 30;
+        }
+        return Some((score, vec![[start, start + len]]));
+    }
+
+    // The characters in order, anywhere
+    let mut score = 0;
+    let mut ranges: Vec<[usize; 2]> = Vec::new();
+    let mut next = term.iter().peekable();
+    for (index, c) in text.iter().enumerate() {
+        if next.peek() != Some(&c) {
+            continue;
+        }
+        next.next();
+        score += 10;
+        if is_word_start(&text, index) {
+            score += 8;
+        }
+        match ranges.last_mut() {
+            Some(range) if range[1] == index => {
+                range[1] += 1;
+                score += 5;
+            }
+            _ => ranges.push([index, index + 1]),
+        }
+    }
+    if next.peek().is_some() {
+        return None;
+    }
+    // Scattered matches rank below every whole one
+    Some((score.min(99), ranges))
+}
+
+fn is_word_start(text: &[char], index: usize) -> bool {
+    index == 0 || !text[index - 1].is_alphanumeric()
+}
+
+/// One lowercase character per character, so indexes into the text stay valid
+fn lowercase(text: &str) -> Vec<char> {
+    text.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect()
+}
+
+fn merge_ranges(a: &[[usize; 2]], b: &[[usize; 2]]) -> Vec<[usize; 2]> {
+    let mut ranges = [a, b].concat();
+    ranges.sort();
+    let mut merged: Vec<[usize; 2]> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range[0] <= last[1] => last[1] = last[1].max(range[1]),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(id: &str, issuer: &str, name: &str, tags: &[&str]) -> Service {
+        Service {
+            id: String::from(id),
+            issuer: String::from(issuer),
+            name: String::from(name),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn ids(matches: &[SearchMatch]) -> Vec<&str> {
+        matches.iter().map(|found| found.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        let term = lowercase("hub");
+        assert_eq!(fuzzy_match(&term, "GitHub").unwrap().1, vec![[3, 6]]);
+        let (scattered, ranges) = fuzzy_match(&lowercase("gthb"), "GitHub").unwrap();
+        assert_eq!(ranges, vec![[0, 1], [2, 4], [5, 6]]);
+        assert!(scattered < fuzzy_match(&term, "GitHub").unwrap().0);
+        assert!(fuzzy_match(&lowercase("hubg"), "GitHub").is_none());
+        // Whole words rank above parts of words
+        assert!(fuzzy_match(&term, "Hub Cloud").unwrap().0 > fuzzy_match(&term, "GitHub").unwrap().0);
+    }
+
+    #[test]
+    fn test_search_services() {
+        let services = [
+            service("github", "GitHub", "alice@example.com", &["work"]),
+            service("gitlab", "GitLab", "alice", &[]),
+            service("bank", "Bank", "alice", &["finance"]),
+        ];
+
+        assert_eq!(ids(&search_services(&services, "git")), vec!["github", "gitlab"]);
+        assert_eq!(ids(&search_services(&services, "gtlb")), vec!["gitlab"]);
+        assert!(search_services(&services, "  ").is_empty());
+
+        // Every word must match, in any field
+        let found = search_services(&services, "git work");
+        assert_eq!(ids(&found), vec!["github"]);
+        assert_eq!(found[0].highlights[1], Highlight {
+            field: SearchField::Tag,
+            value: String::from("work"),
+            ranges: vec![[0, 4]],
+        });
+
+        // The issuer counts more than the account
+        let services = [service("a", "Mail", "bank", &[]), service("b", "Bank", "mail", &[])];
+        assert_eq!(ids(&search_services(&services, "bank")), vec!["b", "a"]);
+    }
+}
@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::error::RauthyError;
+use crate::organize::SortMode;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// App settings. They aren't secret, so they are stored as plain JSON next to the vault.
+///
+/// Every field has a default, so settings files written by older versions still load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Minutes without activity before the vault is locked. `0` disables the idle lock
+    pub auto_lock_minutes: u64,
+    /// Locks the vault when the system is going to sleep/suspend
+    pub lock_on_sleep: bool,
+    /// Locks the vault when the session/screen is locked
+    pub lock_on_screen_lock: bool,
+    /// Asks for the password again before revealing the secret of a service
+    pub require_password_to_reveal: bool,
+    /// Deletes the vault after this many failed unlock attempts. `0` disables the wipe
+    pub wipe_after_failed_attempts: u32,
+    /// How the services are listed
+    pub sort_mode: SortMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            auto_lock_minutes: 5,
+            lock_on_sleep: true,
+            lock_on_screen_lock: true,
+            require_password_to_reveal: true,
+            wipe_after_failed_attempts: 0,
+            sort_mode: SortMode::Manual,
+        }
+    }
+}
+
+impl Settings {
+    /// Checks if `other` changes a setting that protects the vault, which requires the password
+    pub fn security_changed(&self, other: &Settings) -> bool {
+        self.auto_lock_minutes != other.auto_lock_minutes
+            || self.require_password_to_reveal != other.require_password_to_reveal
+            || self.wipe_after_failed_attempts != other.wipe_after_failed_attempts
+            || self.lock_on_sleep != other.lock_on_sleep
+            || self.lock_on_screen_lock != other.lock_on_screen_lock
+    }
+
+    /// Loads the settings from `dir`, falling back to the defaults when the file
+    /// doesn't exist or can't be parsed.
+    pub fn load(dir: &Path) -> Self {
+        match fs::read_to_string(dir.join(SETTINGS_FILE)) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                eprintln!("Invalid settings file, using the defaults: {err}");
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), RauthyError> {
+        fs::create_dir_all(dir)?;
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|err| RauthyError::Io(err.to_string()))?;
+        fs::write(dir.join(SETTINGS_FILE), content)?;
+        Ok(())
+    }
+}
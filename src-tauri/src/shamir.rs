@@ -0,0 +1,140 @@
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use crate::error::RauthyError;
+
+/// One share of a secret split with [`split_secret`]. Any `threshold` of them rebuild the
+/// secret, fewer reveal nothing about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    pub threshold: u8,
+    /// The x coordinate of the share: a distinct non-zero element of GF(256), so at most 255 shares
+    pub index: u8,
+    /// The y coordinate for each byte of the secret
+    pub data: Zeroizing<Vec<u8>>,
+}
+
+/// Splits `secret` into `count` shares, so any `threshold` of them can rebuild it.
+///
+/// Each byte of the secret is the constant term of a random polynomial of degree
+/// `threshold - 1` over GF(256), and each share holds the value of the polynomials at its index.
+pub fn split_secret(secret: &[u8], count: u8, threshold: u8) -> Result<Vec<Share>, RauthyError> {
+    if threshold < 2 || threshold > count {
+        return Err(RauthyError::InvalidShares("The threshold must be between 2 and the number of shares"));
+    }
+
+    let mut shares: Vec<Share> = (1..=count)
+        .map(|index| Share {
+            threshold,
+            index,
+            data: Zeroizing::new(Vec::with_capacity(secret.len())),
+        })
+        .collect();
+
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for byte in secret {
+        coefficients[0] = *byte;
+        rand::rng().fill_bytes(&mut coefficients[1..]);
+        for share in shares.iter_mut() {
+            share.data.push(evaluate(&coefficients, share.index));
+        }
+    }
+    Ok(shares)
+}
+
+/// Rebuilds the secret from at least `threshold` shares, using Lagrange interpolation at 0.
+pub fn combine_shares(shares: &[Share]) -> Result<Zeroizing<Vec<u8>>, RauthyError> {
+    let first = shares.first().ok_or(RauthyError::InvalidShares("No shares were provided"))?;
+    if shares.len() < first.threshold as usize {
+        return Err(RauthyError::InvalidShares("Not enough shares to rebuild the recovery key"));
+    }
+    for (position, share) in shares.iter().enumerate() {
+        if share.threshold != first.threshold || share.data.len() != first.data.len() {
+            return Err(RauthyError::InvalidShares("The shares belong to different recovery keys"));
+        }
+        if share.index == 0 || shares[..position].iter().any(|other| other.index == share.index) {
+            return Err(RauthyError::InvalidShares("The same share was provided twice"));
+        }
+    }
+
+    let shares = &shares[..first.threshold as usize];
+    let mut secret = Zeroizing::new(vec![0u8; first.data.len()]);
+    for share in shares {
+        // Lagrange basis polynomial of this share, evaluated at x = 0
+        let mut basis = 1u8;
+        for other in shares.iter().filter(|other| other.index != share.index) {
+            basis = gf_mul(basis, gf_mul(other.index, gf_inv(other.index ^ share.index)));
+        }
+        for (byte, y) in secret.iter_mut().zip(share.data.iter()) {
+            *byte ^= gf_mul(*y, basis);
+        }
+    }
+    Ok(secret)
+}
+
+/// Evaluates the polynomial at `x` with Horner's method
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0u8, |result, coefficient| gf_mul(result, x) ^ coefficient)
+}
+
+/// Multiplication in GF(256) with the AES polynomial, without branches on the values
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = a >> 7;
+        a = (a << 1) ^ (0x1b & carry.wrapping_neg());
+        b >>= 1;
+    }
+    product
+}
+
+/// Inverse in GF(256): a^254, as a^255 = 1
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gf_inv() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_split_and_combine() {
+        let secret = [42u8; 32];
+        let shares = split_secret(&secret, 5, 3).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        assert_eq!(*combine_shares(&shares[..3]).unwrap(), secret);
+        assert_eq!(*combine_shares(&[shares[4].clone(), shares[0].clone(), shares[2].clone()]).unwrap(), secret);
+        assert_eq!(*combine_shares(&shares).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_not_enough_shares() {
+        let shares = split_secret(&[1, 2, 3], 3, 2).unwrap();
+        assert!(combine_shares(&shares[..1]).is_err());
+        assert!(combine_shares(&[shares[0].clone(), shares[0].clone()]).is_err());
+        assert!(split_secret(&[1, 2, 3], 2, 3).is_err());
+        assert!(split_secret(&[1, 2, 3], 3, 1).is_err());
+    }
+}
@@ -1,9 +1,26 @@
 use std::path::PathBuf;
 
+use crate::settings::Settings;
 use crate::storage::Storage;
+use crate::vaults::{VaultEntry, VaultRegistry};
 
 #[derive(Default)]
 pub struct AppState {
     pub storage: Storage,
+    /// The data dir, where the settings and the vault registry are kept
     pub storage_path: PathBuf,
+    pub settings: Settings,
+    pub vaults: VaultRegistry,
+}
+
+impl AppState {
+    /// A locked storage for the active vault
+    pub fn active_storage(&self) -> Storage {
+        Storage::at(&self.vault_path(self.vaults.active()))
+    }
+
+    /// The absolute path of the vault file
+    pub fn vault_path(&self, vault: &VaultEntry) -> PathBuf {
+        self.storage_path.join(&vault.path)
+    }
 }
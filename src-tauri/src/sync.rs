@@ -0,0 +1,319 @@
+use ring::digest;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Runtime};
+
+use crate::storage::{ServiceMap, ServiceViewMap};
+use crate::vault::{HiddenCompartment, KeySlot, VaultFile};
+
+/// Event emitted when changes made on other devices were merged into the open vault,
+/// with the [`SyncReport`] and the services
+pub const VAULT_SYNCED_EVENT: &str = "vault-synced";
+
+/// When each service was deleted (unix seconds), by id. They're kept in the vault, so a
+/// service deleted on one device isn't brought back by a copy of the file that still has it.
+pub type Tombstones = HashMap<String, u64>;
+
+/// What changed in the open vault after merging the changes of other devices
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    /// Ids of the services added on other devices
+    pub added: Vec<String>,
+    /// Ids of the services changed on other devices
+    pub updated: Vec<String>,
+    /// Ids of the services deleted on other devices
+    pub removed: Vec<String>,
+    /// Conflict copies left by the sync tool that were merged (and deleted)
+    pub merged_files: Vec<String>,
+    /// Conflict copies that couldn't be decrypted with the key of this vault. They're left as is
+    pub skipped_files: Vec<String>,
+}
+
+impl SyncReport {
+    pub fn is_empty(&self) -> bool {
+        *self == SyncReport::default()
+    }
+
+    /// Adds the changes of another sync, e.g. with the WebDAV server after the local file
+    pub fn extend(&mut self, other: SyncReport) {
+        self.added.extend(other.added);
+        self.updated.extend(other.updated);
+        self.removed.extend(other.removed);
+        self.merged_files.extend(other.merged_files);
+        self.skipped_files.extend(other.skipped_files);
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct VaultSyncedPayload<'a> {
+    report: &'a SyncReport,
+    services: ServiceViewMap,
+}
+
+pub fn emit_synced<R: Runtime>(app: &AppHandle<R>, report: &SyncReport, services: ServiceViewMap) {
+    app.emit(VAULT_SYNCED_EVENT, VaultSyncedPayload { report, services }).ok();
+}
+
+/// The vault file as it was last read or written by this device. It's the common ancestor
+/// to tell the changes made by other devices from the ones made here.
+#[derive(Debug, Clone)]
+pub struct SyncBase {
+    pub hash: [u8; 32],
+    pub slots: Vec<KeySlot>,
+    pub hidden: HiddenCompartment,
+}
+
+impl SyncBase {
+    pub fn new(content: &[u8], file: &VaultFile) -> Self {
+        Self {
+            hash: file_hash(content),
+            slots: file.slots.clone(),
+            hidden: file.hidden.clone(),
+        }
+    }
+}
+
+pub fn file_hash(content: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(digest::digest(&digest::SHA256, content).as_ref());
+    hash
+}
+
+/// Merges the services of another copy of the vault into the local ones, entry by entry:
+/// the last modification wins, and a deletion wins over the modifications made before it.
+/// The position and the uses are merged on their own, as moving or using a service isn't a
+/// modification: the last move wins, and the highest count of uses is kept.
+///
+/// Returns whether the other copy is missing changes made here, i.e. whether the merged
+/// services must be written back.
+pub fn merge_services(
+    local: &mut ServiceMap,
+    local_tombstones: &mut Tombstones,
+    remote: ServiceMap,
+    remote_tombstones: Tombstones,
+    report: &mut SyncReport,
+) -> bool {
+    let mut remote_outdated = local_tombstones
+        .iter()
+        .any(|(id, deleted)| remote_tombstones.get(id).is_none_or(|remote| remote < deleted));
+    for (id, deleted) in remote_tombstones {
+        let tombstone = local_tombstones.entry(id).or_insert(deleted);
+        *tombstone = (*tombstone).max(deleted);
+    }
+
+    let remote_ids: HashSet<String> = remote.keys().cloned().collect();
+    for (id, mut service) in remote {
+        match local.get_mut(&id) {
+            Some(current) => {
+                // The last move wins, whichever copy has the last modification
+                if current.position_modified > service.position_modified {
+                    remote_outdated = true;
+                    service.position = current.position;
+                    service.position_modified = current.position_modified;
+                } else {
+                    current.position = service.position;
+                    current.position_modified = service.position_modified;
+                }
+                remote_outdated |= current.uses > service.uses || current.last_used > service.last_used;
+                service.uses = service.uses.max(current.uses);
+                service.last_used = service.last_used.max(current.last_used);
+                current.uses = service.uses;
+                current.last_used = service.last_used;
+
+                if current.modified >= service.modified {
+                    remote_outdated |= current.modified > service.modified;
+                } else {
+                    report.updated.push(id.clone());
+                    *current = service;
+                }
+            }
+            // Deleted here after it was last changed on the other device
+            None if local_tombstones.get(&id).is_some_and(|deleted| *deleted >= service.modified) => {
+                remote_outdated = true;
+            }
+            None => {
+                report.added.push(id.clone());
+                local.insert(id, service);
+            }
+        }
+    }
+
+    local.retain(|id, service| {
+        if remote_ids.contains(id) {
+            return true;
+        }
+        if local_tombstones.get(id).is_some_and(|deleted| *deleted >= service.modified) {
+            report.removed.push(id.clone());
+            return false;
+        }
+        remote_outdated = true;
+        true
+    });
+    // Services added again after being deleted
+    local_tombstones.retain(|id, _| !local.contains_key(id));
+
+    remote_outdated
+}
+
+/// The conflict copies of the vault left by sync tools, next to it: e.g.
+/// `Rauthy.sync-conflict-20250101-120000-ABC1234.bin` (Syncthing) or
+/// `Rauthy (Laptop's conflicted copy 2025-01-01).bin` (Dropbox).
+pub fn conflict_copies(vault_path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (vault_path.parent(), vault_path.file_stem().and_then(|stem| stem.to_str())) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut copies: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path != vault_path && path.extension() == vault_path.extension())
+        .filter(|path| {
+            path.file_stem()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(stem))
+                .is_some_and(|suffix| suffix.to_lowercase().contains("conflict"))
+        })
+        .collect();
+    copies.sort();
+    copies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Service;
+
+    fn service(id: &str, modified: u64) -> (String, Service) {
+        let mut service = Service::default();
+        service.id = id.to_string();
+        service.modified = modified;
+        (id.to_string(), service)
+    }
+
+    #[test]
+    fn test_merge_keeps_last_change() {
+        let mut local = ServiceMap::from([service("both", 20), service("local", 10), service("older", 5)]);
+        let remote = ServiceMap::from([service("both", 10), service("remote", 10), service("older", 15)]);
+        let mut report = SyncReport::default();
+
+        let outdated = merge_services(&mut local, &mut Tombstones::new(), remote, Tombstones::new(), &mut report);
+        assert!(outdated);
+        assert_eq!(local["both"].modified, 20);
+        assert_eq!(local["older"].modified, 15);
+        assert!(local.contains_key("local") && local.contains_key("remote"));
+        assert_eq!(report.added, vec!["remote"]);
+        assert_eq!(report.updated, vec!["older"]);
+    }
+
+    #[test]
+    fn test_merge_position_separately() {
+        // Moved here, edited on the other device
+        let (id, mut moved) = service("moved", 10);
+        moved.position = 3;
+        moved.position_modified = 30;
+        let (_, mut edited) = service("moved", 20);
+        edited.name = String::from("edited");
+        edited.position = 1;
+        edited.position_modified = 5;
+        let mut local = ServiceMap::from([(id.clone(), moved)]);
+        let mut report = SyncReport::default();
+
+        let outdated = merge_services(&mut local, &mut Tombstones::new(), ServiceMap::from([(id, edited)]), Tombstones::new(), &mut report);
+        assert!(outdated);
+        assert_eq!(local["moved"].name, "edited");
+        assert_eq!((local["moved"].position, local["moved"].position_modified), (3, 30));
+        assert_eq!(report.updated, vec!["moved"]);
+
+        // Moved on the other device only: it's not reported as an edit
+        let (id, mut remote) = service("moved", 20);
+        remote.name = String::from("edited");
+        remote.position = 7;
+        remote.position_modified = 40;
+        let mut report = SyncReport::default();
+        let outdated = merge_services(&mut local, &mut Tombstones::new(), ServiceMap::from([(id, remote)]), Tombstones::new(), &mut report);
+        assert!(!outdated);
+        assert_eq!(local["moved"].position, 7);
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_merge_uses() {
+        // Used more here, but edited on the other device
+        let (id, mut used) = service("used", 10);
+        used.uses = 5;
+        used.last_used = 100;
+        let (_, mut edited) = service("used", 20);
+        edited.uses = 3;
+        edited.last_used = 200;
+        let mut local = ServiceMap::from([(id.clone(), used)]);
+        let mut report = SyncReport::default();
+
+        let outdated = merge_services(&mut local, &mut Tombstones::new(), ServiceMap::from([(id.clone(), edited)]), Tombstones::new(), &mut report);
+        assert!(outdated);
+        assert_eq!(local["used"].modified, 20);
+        assert_eq!((local["used"].uses, local["used"].last_used), (5, 200));
+
+        // Used on the other device only
+        let (_, mut remote) = service("used", 20);
+        remote.uses = 8;
+        remote.last_used = 300;
+        let mut report = SyncReport::default();
+        let outdated = merge_services(&mut local, &mut Tombstones::new(), ServiceMap::from([(id, remote)]), Tombstones::new(), &mut report);
+        assert!(!outdated);
+        assert_eq!((local["used"].uses, local["used"].last_used), (8, 300));
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_merge_tombstones() {
+        // Deleted on the other device after the last change here
+        let mut local = ServiceMap::from([service("deleted", 10), service("re-added", 30)]);
+        let remote_tombstones = Tombstones::from([("deleted".to_string(), 20), ("re-added".to_string(), 20)]);
+        let mut tombstones = Tombstones::from([("gone".to_string(), 20)]);
+        let remote = ServiceMap::from([service("gone", 10)]);
+        let mut report = SyncReport::default();
+
+        assert!(merge_services(&mut local, &mut tombstones, remote, remote_tombstones, &mut report));
+        assert!(!local.contains_key("deleted") && !local.contains_key("gone"));
+        assert!(local.contains_key("re-added"));
+        assert_eq!(report.removed, vec!["deleted"]);
+        assert_eq!(tombstones, Tombstones::from([("deleted".to_string(), 20), ("gone".to_string(), 20)]));
+    }
+
+    #[test]
+    fn test_merge_same_content_is_not_outdated() {
+        let mut local = ServiceMap::from([service("a", 10)]);
+        let mut tombstones = Tombstones::from([("b".to_string(), 5)]);
+        let remote_tombstones = tombstones.clone();
+        let mut report = SyncReport::default();
+
+        let outdated = merge_services(&mut local, &mut tombstones, ServiceMap::from([service("a", 10)]), remote_tombstones, &mut report);
+        assert!(!outdated);
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_conflict_copies() {
+        let dir = std::env::temp_dir().join(format!("rauthy-sync-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let names = [
+            "Rauthy.bin",
+            "Rauthy.bin.backup",
+            "Rauthy.sync-conflict-20250101-120000-ABC1234.bin",
+            "Rauthy (Laptop's conflicted copy 2025-01-01).bin",
+            "Other.sync-conflict-20250101-120000-ABC1234.bin",
+        ];
+        for name in names {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let copies = conflict_copies(&dir.join("Rauthy.bin"));
+        assert_eq!(copies, vec![dir.join(names[3]), dir.join(names[2])]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
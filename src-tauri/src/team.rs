@@ -0,0 +1,190 @@
+use data_encoding::BASE64URL_NOPAD;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, KeyArray, SaltArray};
+use crate::error::RauthyError;
+use crate::vault::{KeySlot, KeySlotKind};
+
+const IDENTITY_FILE: &str = "identity.json";
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// The X25519 key pair that identifies the user in team vaults. The public key is given to
+/// the team, and the secret key is kept encrypted with a key derived from the user's own
+/// password, so nobody has to share a password to open a team vault.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Identity {
+    pub name: String,
+    pub public_key: [u8; PUBLIC_KEY_LEN],
+    salt: SaltArray,
+    /// Nonce + the encrypted secret key
+    encrypted_secret: Vec<u8>,
+}
+
+/// What the frontend gets: the name and the public key to give to the team
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityView {
+    pub name: String,
+    pub public_key: String,
+}
+
+/// A member of a team vault. The list is kept in the encrypted payload, next to the services,
+/// so the vault can be re-keyed for the remaining members when one of them is removed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamMember {
+    pub name: String,
+    /// Encoded with [`format_public_key`]
+    pub public_key: String,
+}
+
+impl Identity {
+    /// Generates a new key pair, protected by `password`
+    pub fn create(name: &str, password: &str) -> Result<Self, RauthyError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(RauthyError::InvalidMember("The name can't be empty"));
+        }
+        let secret = generate_secret();
+        let salt = crypto::generate_salt();
+        let key = crypto::derive_key_from_password_and_salt(password, Some(&salt))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            public_key: PublicKey::from(&secret).to_bytes(),
+            salt,
+            encrypted_secret: crypto::encrypt_data(secret.as_bytes(), key.as_ref())?,
+        })
+    }
+
+    /// Loads the identity of this device from `dir`, if it has one
+    pub fn load(dir: &Path) -> Option<Self> {
+        let content = fs::read_to_string(dir.join(IDENTITY_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), RauthyError> {
+        fs::create_dir_all(dir)?;
+        let content = serde_json::to_string_pretty(self).map_err(|err| RauthyError::Io(err.to_string()))?;
+        fs::write(dir.join(IDENTITY_FILE), content)?;
+        Ok(())
+    }
+
+    /// Decrypts the secret key. Fails with `RauthyError::WrongPassword`.
+    pub fn unlock(&self, password: &str) -> Result<StaticSecret, RauthyError> {
+        let key = crypto::derive_key_from_password_and_salt(password, Some(&self.salt))?;
+        let decrypted = crypto::decrypt_data(&self.encrypted_secret, key.as_ref())?;
+        let mut secret = Zeroizing::new([0u8; 32]);
+        if decrypted.len() != secret.len() {
+            return Err(RauthyError::CorruptVault);
+        }
+        secret.copy_from_slice(&decrypted);
+        Ok(StaticSecret::from(*secret))
+    }
+
+    pub fn view(&self) -> IdentityView {
+        IdentityView {
+            name: self.name.clone(),
+            public_key: format_public_key(&self.public_key),
+        }
+    }
+}
+
+pub fn format_public_key(public_key: &[u8; PUBLIC_KEY_LEN]) -> String {
+    BASE64URL_NOPAD.encode(public_key)
+}
+
+pub fn parse_public_key(text: &str) -> Result<[u8; PUBLIC_KEY_LEN], RauthyError> {
+    BASE64URL_NOPAD
+        .decode(text.trim().as_bytes())
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or(RauthyError::InvalidMember("The public key is malformed"))
+}
+
+pub fn generate_secret() -> StaticSecret {
+    let mut bytes = Zeroizing::new([0u8; 32]);
+    rand::rng().fill_bytes(bytes.as_mut());
+    StaticSecret::from(*bytes)
+}
+
+/// Wraps the data key for a member: the key encryption key is derived from the X25519
+/// agreement between a new ephemeral key and the member's public key. The ephemeral public
+/// key is kept in the slot salt, so only the member's secret key can unwrap it.
+pub fn wrap_for_member(member_public_key: &[u8; PUBLIC_KEY_LEN], data_key: &KeyArray) -> Result<KeySlot, RauthyError> {
+    let ephemeral = generate_secret();
+    let ephemeral_public_key = PublicKey::from(&ephemeral).to_bytes();
+    let key = member_key(&ephemeral, member_public_key, &ephemeral_public_key, member_public_key)?;
+    KeySlot::wrap(KeySlotKind::Member, ephemeral_public_key, &key, data_key)
+}
+
+/// Finds the slot of the member with `secret` and unwraps the data key
+pub fn unwrap_member_slot(slots: &[KeySlot], secret: &StaticSecret) -> Result<Zeroizing<KeyArray>, RauthyError> {
+    let public_key = PublicKey::from(secret).to_bytes();
+    slots
+        .iter()
+        .filter(|slot| slot.kind == KeySlotKind::Member)
+        .find_map(|slot| {
+            let key = member_key(secret, &slot.salt, &slot.salt, &public_key).ok()?;
+            slot.unwrap_key(&key).ok()
+        })
+        .ok_or(RauthyError::InvalidMember("You aren't a member of this vault"))
+}
+
+/// The key encryption key of a member slot, from the agreement between `secret` and the
+/// other side's public key. It's salted with the ephemeral and the member public keys.
+fn member_key(
+    secret: &StaticSecret,
+    peer_public_key: &[u8; PUBLIC_KEY_LEN],
+    ephemeral_public_key: &[u8; PUBLIC_KEY_LEN],
+    member_public_key: &[u8; PUBLIC_KEY_LEN],
+) -> Result<Zeroizing<KeyArray>, RauthyError> {
+    let shared_secret = secret.diffie_hellman(&PublicKey::from(*peer_public_key));
+    // Low order points give a shared secret anyone can compute
+    if !shared_secret.was_contributory() {
+        return Err(RauthyError::InvalidMember("The public key is malformed"));
+    }
+    let salt = [ephemeral_public_key.as_slice(), member_public_key].concat();
+    crypto::derive_key_from_secret_and_salt(shared_secret.as_bytes(), &salt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_unlock() {
+        let identity = Identity::create("Alice", "alice_password").unwrap();
+        let secret = identity.unlock("alice_password").unwrap();
+        assert_eq!(PublicKey::from(&secret).to_bytes(), identity.public_key);
+        assert!(matches!(identity.unlock("wrong"), Err(RauthyError::WrongPassword)));
+        assert_eq!(parse_public_key(&identity.view().public_key).unwrap(), identity.public_key);
+        assert!(Identity::create(" ", "password").is_err());
+    }
+
+    #[test]
+    fn test_member_slots() {
+        let data_key = crypto::generate_key();
+        let alice = Identity::create("Alice", "alice_password").unwrap();
+        let bob = Identity::create("Bob", "bob_password").unwrap();
+        let carol = Identity::create("Carol", "carol_password").unwrap();
+        let slots = vec![
+            wrap_for_member(&alice.public_key, &data_key).unwrap(),
+            wrap_for_member(&bob.public_key, &data_key).unwrap(),
+        ];
+
+        for member in [&alice, &bob] {
+            let secret = member.unlock(&format!("{}_password", member.name.to_lowercase())).unwrap();
+            assert_eq!(*unwrap_member_slot(&slots, &secret).unwrap(), *data_key);
+        }
+        let carol_secret = carol.unlock("carol_password").unwrap();
+        assert!(unwrap_member_slot(&slots, &carol_secret).is_err());
+        assert!(wrap_for_member(&[0; PUBLIC_KEY_LEN], &data_key).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::state::AppState;
+use crate::totp::*;
+
+/// Event emitted with the tokens of the services that changed since the last emission
+pub const TOKENS_UPDATED_EVENT: &str = "tokens-updated";
+
+/// Next step of the services that can't generate tokens. They are only retried after a refresh.
+const NEVER: u64 = u64::MAX;
+
+pub enum TickerMessage {
+    /// The services in the storage changed (unlock, add, update, remove...), so every
+    /// token must be recalculated and sent again.
+    Refresh,
+}
+
+/// Handle to the background thread that pushes token updates to the frontend.
+///
+/// The thread sleeps until the closest `next_step_time` among the stored services and
+/// only wakes up earlier when it receives a [`TickerMessage`]. When the storage is
+/// locked or empty it blocks without any timeout, so there is no periodic wakeup.
+pub struct TokenTicker {
+    sender: Sender<TickerMessage>,
+}
+
+impl TokenTicker {
+    pub fn refresh(&self) {
+        // The receiver only goes away when the app is exiting
+        self.sender.send(TickerMessage::Refresh).ok();
+    }
+}
+
+/// Starts the token ticker thread. `AppState` must already be managed by the app.
+pub fn spawn_token_ticker<R: Runtime>(app: AppHandle<R>) -> TokenTicker {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || run_ticker(app, receiver));
+    TokenTicker { sender }
+}
+
+/// Asks the ticker to resend all tokens. Should be called after any change to the services.
+pub fn refresh_tokens<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(ticker) = app.try_state::<TokenTicker>() {
+        ticker.refresh();
+    }
+}
+
+fn run_ticker<R: Runtime>(app: AppHandle<R>, receiver: Receiver<TickerMessage>) {
+    // The next_step_time of the last token sent for each service
+    let mut next_steps: HashMap<String, u64> = HashMap::new();
+
+    loop {
+        let changed = collect_changed_tokens(&app, &mut next_steps);
+        if !changed.is_empty() {
+            if let Err(err) = app.emit(TOKENS_UPDATED_EVENT, &changed) {
+                eprintln!("Couldn't emit the tokens-updated event: {err}");
+            }
+        }
+
+        let message = match next_steps.values().filter(|step| **step != NEVER).min() {
+            Some(next_step) => {
+                let wait = (*next_step * 1000).saturating_sub(now_millis());
+                match receiver.recv_timeout(Duration::from_millis(wait)) {
+                    Ok(message) => Some(message),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            None => match receiver.recv() {
+                Ok(message) => Some(message),
+                Err(_) => return,
+            },
+        };
+
+        if let Some(TickerMessage::Refresh) = message {
+            next_steps.clear();
+        }
+    }
+}
+
+/// Calculates the tokens of the services that are new or whose step already expired,
+/// updating `next_steps` and forgetting the services that were removed.
+fn collect_changed_tokens<R: Runtime>(
+    app: &AppHandle<R>,
+    next_steps: &mut HashMap<String, u64>,
+) -> HashMap<String, TokenState> {
+    let app_state = app.state::<Mutex<AppState>>();
+    let state = app_state.lock().unwrap();
+    let services = state.storage.services();
+
+    next_steps.retain(|id, _| services.contains_key(id));
+
+    let now = now_millis() / 1000;
+    let mut changed = HashMap::new();
+    for id in services.keys() {
+        if next_steps.get(id).is_some_and(|next_step| *next_step > now) {
+            continue;
+        }
+        let Some(token) = state.storage.service_token(id) else {
+            continue;
+        };
+        let next_step = token.as_ref().map_or(NEVER, |token| token.next_step_time);
+        next_steps.insert(id.clone(), next_step);
+        changed.insert(id.clone(), TokenState::from(token));
+    }
+    changed
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -1,14 +1,17 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use totp_rs::TOTP;
 
-type Error = &'static str;
+use crate::error::RauthyError;
+
+type Error = RauthyError;
 
 pub trait ServiceToken {
     fn current_totp(&self) -> Result<TotpToken, Error>;
 }
 
 pub trait ServicesTokens {
-    fn services_tokens(&self) -> Result<HashMap<String, TotpToken>, ()>;
+    fn services_tokens(&self) -> HashMap<String, TokenState>;
 }
 
 #[derive(Serialize, Deserialize)]
@@ -16,3 +19,32 @@ pub struct TotpToken {
     pub token: String,
     pub next_step_time: u64,
 }
+
+/// The token of a service, or the reason it couldn't be generated.
+/// A service with a broken secret reports its error without affecting the others.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum TokenState {
+    Token(TotpToken),
+    Error { error: Error },
+}
+
+impl From<Result<TotpToken, Error>> for TokenState {
+    fn from(result: Result<TotpToken, Error>) -> Self {
+        match result {
+            Ok(token) => TokenState::Token(token),
+            Err(error) => TokenState::Error { error },
+        }
+    }
+}
+
+impl ServiceToken for TOTP {
+    fn current_totp(&self) -> Result<TotpToken, Error> {
+        Ok(TotpToken {
+            token: self.generate_current().map_err(|_| RauthyError::TokenGeneration)?,
+            next_step_time: self
+                .next_step_current()
+                .map_err(|_| RauthyError::TokenGeneration)?,
+        })
+    }
+}
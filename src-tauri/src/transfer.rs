@@ -0,0 +1,575 @@
+use data_encoding::BASE64URL_NOPAD;
+use qrcode::{render::svg, QrCode};
+use rand::RngCore;
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::hkdf;
+use ring::rand::SystemRandom;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use url::Url;
+use zeroize::Zeroizing;
+
+use crate::audit::AuditAction;
+use crate::crypto::{self, KeyArray};
+use crate::error::RauthyError;
+use crate::state::AppState;
+use crate::storage::{Service, ServiceMap, ServiceViewMap};
+use crate::ticker::refresh_tokens;
+
+/// Event emitted on both devices when they're connected, with the short authentication
+/// string the user must compare before the services are sent
+pub const TRANSFER_PAIRED_EVENT: &str = "transfer-paired";
+/// Event emitted on both devices when the transfer ends, with how many services were sent
+/// (or received) or the error. The receiving device also gets its services
+pub const TRANSFER_FINISHED_EVENT: &str = "transfer-finished";
+
+const TRANSFER_SCHEME: &str = "rauthy-transfer";
+const PUBLIC_KEY_LEN: usize = 32;
+const TOKEN_LEN: usize = 16;
+/// How long the QR code can be scanned, and how long the user has to compare the codes
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(300);
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest message accepted from the other device
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+/// Sent back by the receiving device once the services are saved
+const ACK: &[u8] = b"saved";
+
+/// What the sending device shows: the QR code, and its text for devices without a camera
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferOffer {
+    pub uri: String,
+    pub qr_code: String,
+}
+
+/// The content of the QR code: where the sending device listens, its ephemeral public key
+/// and a random token. The QR code is the authenticated channel: only the device that scans
+/// it knows the token, and it can't be tricked into talking to another device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferUri {
+    pub address: SocketAddr,
+    pub public_key: [u8; PUBLIC_KEY_LEN],
+    pub token: [u8; TOKEN_LEN],
+}
+
+impl TransferUri {
+    pub fn parse(text: &str) -> Result<Self, RauthyError> {
+        const INVALID: RauthyError = RauthyError::Transfer("This isn't the QR code of a transfer");
+
+        let url = Url::parse(text.trim()).map_err(|_| INVALID)?;
+        if url.scheme() != TRANSFER_SCHEME {
+            return Err(INVALID);
+        }
+        let address = format!("{}:{}", url.host_str().ok_or(INVALID)?, url.port().ok_or(INVALID)?)
+            .parse()
+            .map_err(|_| INVALID)?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .and_then(|(_, value)| BASE64URL_NOPAD.decode(value.as_bytes()).ok())
+        };
+
+        Ok(Self {
+            address,
+            public_key: param("key").and_then(|key| key.try_into().ok()).ok_or(INVALID)?,
+            token: param("token").and_then(|token| token.try_into().ok()).ok_or(INVALID)?,
+        })
+    }
+
+    pub fn to_uri(&self) -> String {
+        format!(
+            "{}://{}?key={}&token={}",
+            TRANSFER_SCHEME,
+            self.address,
+            BASE64URL_NOPAD.encode(&self.public_key),
+            BASE64URL_NOPAD.encode(&self.token)
+        )
+    }
+}
+
+/// The keys of each direction of the channel, and the code shown on both devices
+struct Session {
+    sender_key: Zeroizing<KeyArray>,
+    receiver_key: Zeroizing<KeyArray>,
+    sas: String,
+}
+
+/// The confirmation of the transfer being sent, if any. Managed by the app.
+#[derive(Default)]
+pub struct PendingTransfer(Mutex<Option<Sender<bool>>>);
+
+impl PendingTransfer {
+    /// Sends the services after the user checked that both devices show the same code, or
+    /// cancels the transfer
+    pub fn confirm(&self, confirmed: bool) -> Result<(), RauthyError> {
+        let sender = self.0.lock().unwrap().take().ok_or(RauthyError::NotFound)?;
+        // The transfer already failed or timed out when the receiver is gone
+        sender.send(confirmed).map_err(|_| RauthyError::NotFound)
+    }
+
+    /// Replaces the previous transfer, which is cancelled
+    fn replace(&self, sender: Sender<bool>) {
+        *self.0.lock().unwrap() = Some(sender);
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct TransferPairedPayload<'a> {
+    sas: &'a str,
+}
+
+#[derive(Clone, Serialize)]
+struct TransferFinishedPayload {
+    count: usize,
+    error: Option<RauthyError>,
+    services: Option<ServiceViewMap>,
+    #[serde(flatten)]
+    collisions: Collisions,
+}
+
+/// The received services whose id was already in the vault, by their labels
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+struct Collisions {
+    /// Same account as the one in the vault, so they weren't added again
+    skipped: Vec<String>,
+    /// Another account with the same issuer and name (e.g. 2FA was reset on one of the
+    /// devices), so they were added with a new id
+    renamed: Vec<String>,
+}
+
+/// Listens on the local network for the device that scans the returned QR code, and sends
+/// it `services` once the user confirms the codes match (see [`PendingTransfer::confirm`]).
+pub fn start_sending<R: Runtime>(
+    app: &AppHandle<R>,
+    services: Vec<Service>,
+    on_finished: impl FnOnce(&Result<usize, RauthyError>) + Send + 'static,
+) -> Result<TransferOffer, RauthyError> {
+    let listener = TcpListener::bind("0.0.0.0:0").map_err(network)?;
+    let port = listener.local_addr().map_err(network)?.port();
+    let (private_key, public_key) = generate_key_pair()?;
+    let mut token = [0u8; TOKEN_LEN];
+    rand::rng().fill_bytes(&mut token);
+
+    let uri = TransferUri {
+        address: SocketAddr::new(local_ip()?, port),
+        public_key,
+        token,
+    };
+    let offer = TransferOffer {
+        uri: uri.to_uri(),
+        qr_code: QrCode::new(uri.to_uri().as_bytes())
+            .map_err(|_| RauthyError::Transfer("Couldn't render the QR code"))?
+            .render::<svg::Color>()
+            .min_dimensions(256, 256)
+            .build(),
+    };
+
+    let (sender, confirmation) = mpsc::channel();
+    app.state::<PendingTransfer>().replace(sender);
+    let app = app.clone();
+    thread::spawn(move || {
+        let result = accept(&listener, &confirmation).and_then(|mut stream| {
+            send(&mut stream, private_key, &uri, &services, |sas| {
+                app.emit(TRANSFER_PAIRED_EVENT, TransferPairedPayload { sas }).ok();
+                matches!(confirmation.recv_timeout(PAIRING_TIMEOUT), Ok(true))
+            })
+        });
+        on_finished(&result);
+        emit_finished(&app, result, None, Collisions::default());
+    });
+    Ok(offer)
+}
+
+/// Connects to the device that showed the QR code with `uri`, and saves the services it
+/// sends into the open vault
+pub fn start_receiving<R: Runtime>(app: &AppHandle<R>, uri: &str) -> Result<(), RauthyError> {
+    let uri = TransferUri::parse(uri)?;
+    let app = app.clone();
+    thread::spawn(move || {
+        let mut services_views = None;
+        let mut collisions = Collisions::default();
+        let result = TcpStream::connect_timeout(&uri.address, IO_TIMEOUT)
+            .map_err(network)
+            .and_then(|mut stream| {
+                receive(
+                    &mut stream,
+                    &uri,
+                    |sas| {
+                        app.emit(TRANSFER_PAIRED_EVENT, TransferPairedPayload { sas }).ok();
+                    },
+                    |services| {
+                        let app_state = app.state::<Mutex<AppState>>();
+                        let mut state = app_state.lock().unwrap();
+                        if !state.storage.is_unlocked() {
+                            return Err(RauthyError::VaultLocked);
+                        }
+                        let (services, found) = resolve_collisions(services, state.storage.services());
+                        collisions = found;
+                        let count = services.len();
+                        for service in services {
+                            state.storage.add_service(service);
+                        }
+                        let result = state.storage.save_to_file(&app);
+                        let target = Some(format!("{} services from another device", count));
+                        state.storage.audit(&app, AuditAction::Import, target, result.as_ref().err());
+                        result?;
+                        services_views = Some(state.storage.service_views());
+                        Ok(count)
+                    },
+                )
+            });
+        if result.is_ok() {
+            refresh_tokens(&app);
+        }
+        emit_finished(&app, result, services_views, collisions);
+    });
+    Ok(())
+}
+
+/// Picks the received services to add to the vault. They never replace the services that
+/// are already there: the same account is skipped, and another one with the same id gets a
+/// new id so both are kept.
+fn resolve_collisions(services: Vec<Service>, existing: &ServiceMap) -> (Vec<Service>, Collisions) {
+    let mut taken: HashSet<String> = existing.keys().cloned().collect();
+    let mut collisions = Collisions::default();
+    let mut added = Vec::with_capacity(services.len());
+    for mut service in services {
+        if taken.contains(&service.id) {
+            if existing.get(&service.id).is_some_and(|current| same_account(current, &service)) {
+                collisions.skipped.push(service.label());
+                continue;
+            }
+            let mut suffix = 2;
+            while taken.contains(&format!("{}-{}", service.id, suffix)) {
+                suffix += 1;
+            }
+            service.id = format!("{}-{}", service.id, suffix);
+            collisions.renamed.push(service.label());
+        }
+        taken.insert(service.id.clone());
+        added.push(service);
+    }
+    (added, collisions)
+}
+
+fn same_account(current: &Service, received: &Service) -> bool {
+    current.secret == received.secret
+        && current.algorithm == received.algorithm
+        && current.digits == received.digits
+        && current.period == received.period
+}
+
+fn emit_finished<R: Runtime>(
+    app: &AppHandle<R>,
+    result: Result<usize, RauthyError>,
+    services: Option<ServiceViewMap>,
+    collisions: Collisions,
+) {
+    let payload = match result {
+        Ok(count) => TransferFinishedPayload { count, error: None, services, collisions },
+        Err(err) => TransferFinishedPayload {
+            count: 0,
+            error: Some(err),
+            services: None,
+            collisions: Collisions::default(),
+        },
+    };
+    app.emit(TRANSFER_FINISHED_EVENT, payload).ok();
+}
+
+/// Waits for the receiving device, until the transfer is cancelled or replaced. Only one
+/// device can connect: a device that isn't the one that scanned the QR code can't guess
+/// the code shown to the user, and doesn't get a second try.
+fn accept(listener: &TcpListener, confirmation: &Receiver<bool>) -> Result<TcpStream, RauthyError> {
+    listener.set_nonblocking(true).map_err(network)?;
+    let deadline = Instant::now() + PAIRING_TIMEOUT;
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false).map_err(network)?;
+                return Ok(stream);
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(network(err)),
+        }
+        let cancelled = !matches!(confirmation.try_recv(), Err(TryRecvError::Empty));
+        if cancelled || Instant::now() > deadline {
+            return Err(RauthyError::Transfer("The transfer was cancelled"));
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// The sending side, once the other device connected. `confirm` shows the code and returns
+/// whether the user confirmed it's the one shown on the other device. Returns how many
+/// services were sent.
+fn send(
+    stream: &mut TcpStream,
+    private_key: EphemeralPrivateKey,
+    uri: &TransferUri,
+    services: &[Service],
+    confirm: impl FnOnce(&str) -> bool,
+) -> Result<usize, RauthyError> {
+    stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(network)?;
+    stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(network)?;
+    let mut receiver_key = [0u8; PUBLIC_KEY_LEN];
+    stream.read_exact(&mut receiver_key).map_err(network)?;
+    let session = derive_session(private_key, &receiver_key, &uri.public_key, &receiver_key, &uri.token)?;
+
+    if !confirm(&session.sas) {
+        return Err(RauthyError::Transfer("The transfer was cancelled"));
+    }
+    let content = Zeroizing::new(
+        serde_json::to_vec(services).map_err(|_| RauthyError::Crypto("Couldn't serialize the services"))?,
+    );
+    write_message(stream, &session.sender_key, &content)?;
+
+    // The receiving device saves the services before acknowledging them
+    stream.set_read_timeout(Some(PAIRING_TIMEOUT)).map_err(network)?;
+    if read_message(stream, &session.receiver_key)?.as_slice() != ACK {
+        return Err(RauthyError::Transfer("The other device didn't save the services"));
+    }
+    Ok(services.len())
+}
+
+/// The receiving side. `paired` shows the code, while the user compares it on the sending
+/// device. `save` stores the services and returns how many were saved.
+fn receive(
+    stream: &mut TcpStream,
+    uri: &TransferUri,
+    paired: impl FnOnce(&str),
+    save: impl FnOnce(Vec<Service>) -> Result<usize, RauthyError>,
+) -> Result<usize, RauthyError> {
+    stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(network)?;
+    let (private_key, public_key) = generate_key_pair()?;
+    stream.write_all(&public_key).map_err(network)?;
+    let session = derive_session(private_key, &uri.public_key, &uri.public_key, &public_key, &uri.token)?;
+    paired(&session.sas);
+
+    // The user has to compare the codes before the services are sent
+    stream.set_read_timeout(Some(PAIRING_TIMEOUT)).map_err(network)?;
+    let content = read_message(stream, &session.sender_key)?;
+    let mut services: Vec<Service> = serde_json::from_slice(&content)
+        .map_err(|_| RauthyError::Transfer("The other device sent invalid services"))?;
+    for service in &mut services {
+        service.validate()?;
+    }
+
+    let count = save(services)?;
+    write_message(stream, &session.receiver_key, ACK)?;
+    Ok(count)
+}
+
+fn generate_key_pair() -> Result<(EphemeralPrivateKey, [u8; PUBLIC_KEY_LEN]), RauthyError> {
+    const FAILED: RauthyError = RauthyError::Crypto("Couldn't generate the transfer key");
+
+    let private_key = EphemeralPrivateKey::generate(&X25519, &SystemRandom::new()).map_err(|_| FAILED)?;
+    let public_key = private_key.compute_public_key().map_err(|_| FAILED)?;
+    let public_key = public_key.as_ref().try_into().map_err(|_| FAILED)?;
+    Ok((private_key, public_key))
+}
+
+/// Derives the keys of the channel with HKDF, from the X25519 shared secret salted with the
+/// token and both public keys, so a device that didn't scan the QR code ends up with
+/// different keys and a different code.
+fn derive_session(
+    private_key: EphemeralPrivateKey,
+    peer_public_key: &[u8; PUBLIC_KEY_LEN],
+    sender_public_key: &[u8; PUBLIC_KEY_LEN],
+    receiver_public_key: &[u8; PUBLIC_KEY_LEN],
+    token: &[u8; TOKEN_LEN],
+) -> Result<Session, RauthyError> {
+    let salt = [token.as_slice(), sender_public_key, receiver_public_key].concat();
+    agreement::agree_ephemeral(private_key, &UnparsedPublicKey::new(&X25519, peer_public_key), |shared_secret| {
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &salt).extract(shared_secret);
+        Ok(Session {
+            sender_key: expand(&prk, b"rauthy-transfer-sender")?,
+            receiver_key: expand(&prk, b"rauthy-transfer-receiver")?,
+            sas: short_authentication_string(&*expand(&prk, b"rauthy-transfer-sas")?),
+        })
+    })
+    .map_err(|_| RauthyError::Transfer("The other device sent an invalid key"))?
+}
+
+fn expand(prk: &hkdf::Prk, info: &[u8]) -> Result<Zeroizing<KeyArray>, RauthyError> {
+    let info = [info];
+    let mut key = Zeroizing::new([0u8; 32]);
+    prk.expand(&info, hkdf::HKDF_SHA256)
+        .and_then(|okm| okm.fill(key.as_mut()))
+        .map_err(|_| RauthyError::Crypto("Couldn't derive the key"))?;
+    Ok(key)
+}
+
+/// Six digits, in two groups, to compare at a glance
+fn short_authentication_string(key: &KeyArray) -> String {
+    let number = u32::from_be_bytes([key[0], key[1], key[2], key[3]]) % 1_000_000;
+    format!("{:03} {:03}", number / 1000, number % 1000)
+}
+
+/// Encrypts `data` with AES-GCM and writes it with its length
+fn write_message(stream: &mut TcpStream, key: &KeyArray, data: &[u8]) -> Result<(), RauthyError> {
+    let encrypted = crypto::encrypt_data(data, key)?;
+    stream.write_all(&(encrypted.len() as u32).to_be_bytes()).map_err(network)?;
+    stream.write_all(&encrypted).map_err(network)
+}
+
+fn read_message(stream: &mut TcpStream, key: &KeyArray) -> Result<Zeroizing<Vec<u8>>, RauthyError> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).map_err(network)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(RauthyError::Transfer("The other device sent too much data"));
+    }
+    let mut encrypted = vec![0u8; len];
+    stream.read_exact(&mut encrypted).map_err(network)?;
+    crypto::decrypt_data(&encrypted, key)
+        .map_err(|_| RauthyError::Transfer("The data wasn't sent by the paired device"))
+}
+
+/// The address of this device on the local network: the one of the interface with the
+/// default route. Connecting a UDP socket doesn't send anything.
+fn local_ip() -> Result<IpAddr, RauthyError> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(network)?;
+    socket
+        .connect("192.0.2.1:9")
+        .and_then(|_| socket.local_addr())
+        .map(|address| address.ip())
+        .map_err(|_| RauthyError::Network("This device isn't connected to a local network".to_string()))
+}
+
+fn network(err: std::io::Error) -> RauthyError {
+    RauthyError::Network(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(listener: &TcpListener) -> (EphemeralPrivateKey, TransferUri) {
+        let (private_key, public_key) = generate_key_pair().unwrap();
+        let uri = TransferUri {
+            address: listener.local_addr().unwrap(),
+            public_key,
+            token: [7; TOKEN_LEN],
+        };
+        (private_key, uri)
+    }
+
+    fn services() -> Vec<Service> {
+        vec![Service {
+            id: String::from("github"),
+            issuer: String::from("GitHub"),
+            secret: Zeroizing::new(String::from("JBSWY3DPEHPK3PXP")),
+            tags: vec![String::from("work")],
+            folder: Some(String::from("Code")),
+            favorite: true,
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn test_received_services_dont_replace_the_vault_ones() {
+        let mut existing = ServiceMap::new();
+        for service in services() {
+            existing.insert(service.id.clone(), service);
+        }
+        let mut reset = services().remove(0);
+        reset.secret = Zeroizing::new(String::from("GEZDGNBVGY3TQOJQ"));
+        let mut taken = reset.clone();
+        taken.id = String::from("github-2");
+        let new = Service {
+            id: String::from("gitlab"),
+            issuer: String::from("GitLab"),
+            secret: Zeroizing::new(String::from("JBSWY3DPEHPK3PXP")),
+            ..Default::default()
+        };
+
+        let received = vec![services().remove(0), reset, taken, new];
+        let (added, collisions) = resolve_collisions(received, &existing);
+        let ids: Vec<&str> = added.iter().map(|service| service.id.as_str()).collect();
+        // The account that was already there is skipped, the other GitHub ones get new ids
+        assert_eq!(ids, ["github-2", "github-2-2", "gitlab"]);
+        assert_eq!(added[0].secret.as_str(), "GEZDGNBVGY3TQOJQ");
+        assert_eq!(collisions.skipped, ["GitHub ()"]);
+        assert_eq!(collisions.renamed, ["GitHub ()", "GitHub ()"]);
+    }
+
+    #[test]
+    fn test_transfer_uri() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (_, uri) = offer(&listener);
+        assert_eq!(TransferUri::parse(&uri.to_uri()).unwrap(), uri);
+        assert!(TransferUri::parse("otpauth://totp/GitHub?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(TransferUri::parse("rauthy-transfer://127.0.0.1:1234?key=AAAA&token=AAAA").is_err());
+    }
+
+    #[test]
+    fn test_send_services() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (private_key, uri) = offer(&listener);
+        let sender_uri = uri.clone();
+        let sender = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut shown = String::new();
+            let sent = send(&mut stream, private_key, &sender_uri, &services(), |sas| {
+                shown = sas.to_string();
+                true
+            });
+            (sent, shown)
+        });
+
+        let mut shown = String::new();
+        let mut received = Vec::new();
+        let mut stream = TcpStream::connect(uri.address).unwrap();
+        let count = receive(&mut stream, &uri, |sas| shown = sas.to_string(), |services| {
+            received = services;
+            Ok(received.len())
+        })
+        .unwrap();
+
+        let (sent, sender_shown) = sender.join().unwrap();
+        assert_eq!(sent, Ok(1));
+        assert_eq!(count, 1);
+        assert_eq!(shown, sender_shown);
+        assert_eq!(received[0].issuer, "GitHub");
+        assert_eq!(received[0].secret.as_str(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(received[0].tags, ["work"]);
+        assert_eq!(received[0].folder.as_deref(), Some("Code"));
+        assert!(received[0].favorite);
+    }
+
+    #[test]
+    fn test_device_without_the_token_cant_read_the_services() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (private_key, uri) = offer(&listener);
+        let sender_uri = uri.clone();
+        let sender = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut shown = String::new();
+            // The user confirms without comparing the codes
+            let sent = send(&mut stream, private_key, &sender_uri, &services(), |sas| {
+                shown = sas.to_string();
+                true
+            });
+            (sent, shown)
+        });
+
+        let guessed = TransferUri { token: [8; TOKEN_LEN], ..uri };
+        let mut shown = String::new();
+        let mut stream = TcpStream::connect(guessed.address).unwrap();
+        let received = receive(&mut stream, &guessed, |sas| shown = sas.to_string(), |_| Ok(0));
+        drop(stream);
+
+        let (sent, sender_shown) = sender.join().unwrap();
+        assert!(matches!(received, Err(RauthyError::Transfer(_))));
+        assert!(sent.is_err());
+        assert_ne!(shown, sender_shown);
+    }
+}
@@ -0,0 +1,237 @@
+use bincode::{self, config};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, KeyArray, SaltArray};
+use crate::error::RauthyError;
+
+/// First bytes of the files written since the data key is wrapped in key slots.
+/// Files without it were written by older versions (see `Storage::unlock_legacy`).
+const VAULT_MAGIC: &[u8; 8] = b"RAUTHYV3";
+
+/// The decrypted payloads are padded to a multiple of this size, so the size of the
+/// compartments doesn't tell the filler from a decoy
+pub const PAYLOAD_BLOCK_LEN: usize = 1024;
+/// Nonce + AES-GCM tag
+const ENCRYPTION_OVERHEAD: usize = 12 + 16;
+/// Most blocks in the random payload of a filler compartment
+const MAX_FILLER_BLOCKS: usize = 8;
+
+/// The secret that unlocks a key slot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum KeySlotKind {
+    /// Key derived from the master password with PBKDF2
+    Password,
+    /// Key derived from the recovery key with HKDF
+    Recovery,
+    /// Key derived with HKDF from a random secret, kept on the device encrypted by the
+    /// biometric keystore. The password never has to be stored
+    Biometric,
+    /// Key derived with PBKDF2 from the master password combined with the hash of a key
+    /// file. Replaces the `Password` slot when the vault requires a key file
+    PasswordAndKeyFile,
+    /// Key derived from the X25519 agreement with a member of a team vault. The salt holds the
+    /// ephemeral public key (see `team::wrap_for_member`)
+    Member,
+}
+
+impl KeySlotKind {
+    /// Whether the slot is unlocked by the master password (with or without a key file)
+    pub fn is_password(&self) -> bool {
+        matches!(self, KeySlotKind::Password | KeySlotKind::PasswordAndKeyFile)
+    }
+}
+
+/// The vault data key, encrypted with a key derived from one of the user secrets.
+/// Each secret that can unlock the vault has its own slot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeySlot {
+    pub kind: KeySlotKind,
+    pub salt: SaltArray,
+    /// Nonce + the encrypted data key
+    wrapped_key: Vec<u8>,
+}
+
+impl KeySlot {
+    /// Encrypts `data_key` with `key_encryption_key`, derived from the slot secret and `salt`
+    pub fn wrap(
+        kind: KeySlotKind,
+        salt: SaltArray,
+        key_encryption_key: &KeyArray,
+        data_key: &KeyArray,
+    ) -> Result<Self, RauthyError> {
+        Ok(Self {
+            kind,
+            salt,
+            wrapped_key: crypto::encrypt_data(data_key, key_encryption_key)?,
+        })
+    }
+
+    /// Decrypts the data key. Fails with `RauthyError::WrongPassword` if the key doesn't
+    /// belong to this slot.
+    pub fn unwrap_key(&self, key_encryption_key: &KeyArray) -> Result<Zeroizing<KeyArray>, RauthyError> {
+        unwrap_data_key(&self.wrapped_key, key_encryption_key)
+    }
+}
+
+fn unwrap_data_key(wrapped_key: &[u8], key_encryption_key: &KeyArray) -> Result<Zeroizing<KeyArray>, RauthyError> {
+    let data_key = crypto::decrypt_data(wrapped_key, key_encryption_key)?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    if data_key.len() != key.len() {
+        return Err(RauthyError::CorruptVault);
+    }
+    key.copy_from_slice(&data_key);
+    Ok(key)
+}
+
+/// Pads the serialized services with spaces (ignored by the JSON parser) to a multiple of
+/// [`PAYLOAD_BLOCK_LEN`]
+pub fn pad_payload(payload: &mut Vec<u8>) {
+    let padded_len = payload.len().div_ceil(PAYLOAD_BLOCK_LEN).max(1) * PAYLOAD_BLOCK_LEN;
+    payload.resize(padded_len, b' ');
+}
+
+/// Second set of services, with its own data key wrapped by the duress password.
+///
+/// Every file has one. When no duress password is configured, it's filled with random
+/// bytes of the same shape, which can't be told from AES-GCM output. It's kept as is
+/// while the main services are open, like a real decoy would be, so comparing copies of
+/// the file doesn't reveal it either. The key isn't in `slots`, whose kinds are visible.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HiddenCompartment {
+    pub salt: SaltArray,
+    /// Nonce + the encrypted data key
+    wrapped_key: Vec<u8>,
+    /// Nonce + the encrypted services
+    pub payload: Vec<u8>,
+}
+
+impl HiddenCompartment {
+    pub fn new(salt: SaltArray, key_encryption_key: &KeyArray, data_key: &KeyArray, payload: Vec<u8>) -> Result<Self, RauthyError> {
+        Ok(Self {
+            salt,
+            wrapped_key: crypto::encrypt_data(data_key, key_encryption_key)?,
+            payload,
+        })
+    }
+
+    /// Random bytes shaped like a compartment with a few padded blocks of services
+    pub fn filler() -> Self {
+        let mut rng = rand::rng();
+        let mut wrapped_key = vec![0u8; ENCRYPTION_OVERHEAD + 32];
+        rng.fill_bytes(&mut wrapped_key);
+        let blocks = rng.random_range(1..=MAX_FILLER_BLOCKS);
+        let mut payload = vec![0u8; ENCRYPTION_OVERHEAD + blocks * PAYLOAD_BLOCK_LEN];
+        rng.fill_bytes(&mut payload);
+
+        Self {
+            salt: crypto::generate_salt(),
+            wrapped_key,
+            payload,
+        }
+    }
+
+    /// Decrypts the data key. A filler fails the same way a wrong password does.
+    pub fn unwrap_key(&self, key_encryption_key: &KeyArray) -> Result<Zeroizing<KeyArray>, RauthyError> {
+        unwrap_data_key(&self.wrapped_key, key_encryption_key)
+    }
+
+    /// Replaces the password of the compartment, keeping its data key
+    pub fn rewrap(&mut self, salt: SaltArray, key_encryption_key: &KeyArray, data_key: &KeyArray) -> Result<(), RauthyError> {
+        self.salt = salt;
+        self.wrapped_key = crypto::encrypt_data(data_key, key_encryption_key)?;
+        Ok(())
+    }
+}
+
+/// Layout of the storage file: the magic, followed by the key slots, the services
+/// encrypted with the data key and the hidden compartment.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VaultFile {
+    pub slots: Vec<KeySlot>,
+    /// Nonce + the encrypted services (as JSON, so new fields can have defaults)
+    pub payload: Vec<u8>,
+    pub hidden: HiddenCompartment,
+}
+
+impl VaultFile {
+    pub fn slot(&self, kind: KeySlotKind) -> Option<&KeySlot> {
+        self.slots.iter().find(|slot| slot.kind == kind)
+    }
+
+    pub fn password_slot(&self) -> Option<&KeySlot> {
+        self.slots.iter().find(|slot| slot.kind.is_password())
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, RauthyError> {
+        let mut content = VAULT_MAGIC.to_vec();
+        bincode::serde::encode_into_std_write(self, &mut content, config::standard())
+            .map_err(|_| RauthyError::Crypto("Couldn't serialize the storage file"))?;
+        Ok(content)
+    }
+
+    /// Decodes the file content. Returns `None` for files written before the key slots.
+    pub fn decode(content: &[u8]) -> Result<Option<Self>, RauthyError> {
+        let Some(content) = content.strip_prefix(VAULT_MAGIC.as_slice()) else {
+            return Ok(None);
+        };
+        let (file, _) = bincode::serde::decode_from_slice(content, config::standard())
+            .map_err(|_| RauthyError::CorruptVault)?;
+        Ok(Some(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_unwrap_key() {
+        let data_key = crypto::generate_key();
+        let key_encryption_key = crypto::generate_key();
+        let slot = KeySlot::wrap(KeySlotKind::Password, crypto::generate_salt(), &key_encryption_key, &data_key).unwrap();
+
+        assert_eq!(*slot.unwrap_key(&key_encryption_key).unwrap(), *data_key);
+        assert_eq!(slot.unwrap_key(&crypto::generate_key()), Err(RauthyError::WrongPassword));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let key = crypto::generate_key();
+        let file = VaultFile {
+            slots: vec![KeySlot::wrap(KeySlotKind::Recovery, crypto::generate_salt(), &key, &key).unwrap()],
+            payload: vec![1, 2, 3],
+            hidden: HiddenCompartment::filler(),
+        };
+        let decoded = VaultFile::decode(&file.encode().unwrap()).unwrap().unwrap();
+        assert_eq!(decoded.slots, file.slots);
+        assert_eq!(decoded.payload, file.payload);
+        assert_eq!(decoded.hidden, file.hidden);
+        assert!(decoded.slot(KeySlotKind::Password).is_none());
+
+        // Files of older versions are just the encrypted data
+        assert!(VaultFile::decode(&[0; 64]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_filler_looks_like_a_decoy() {
+        let data_key = crypto::generate_key();
+        let key = crypto::generate_key();
+        let mut payload = b"{}".to_vec();
+        pad_payload(&mut payload);
+        let decoy = HiddenCompartment::new(
+            crypto::generate_salt(),
+            &key,
+            &data_key,
+            crypto::encrypt_data(&payload, data_key.as_ref()).unwrap(),
+        )
+        .unwrap();
+        let filler = HiddenCompartment::filler();
+
+        assert_eq!(decoy.wrapped_key.len(), filler.wrapped_key.len());
+        assert_eq!(decoy.payload.len() % PAYLOAD_BLOCK_LEN, filler.payload.len() % PAYLOAD_BLOCK_LEN);
+        assert_eq!(filler.unwrap_key(&key), Err(RauthyError::WrongPassword));
+        assert_eq!(*decoy.unwrap_key(&key).unwrap(), *data_key);
+    }
+}
@@ -0,0 +1,308 @@
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::audit::AuditLog;
+use crate::error::RauthyError;
+use crate::lockout::FailedAttempts;
+
+const VAULTS_FILE: &str = "vaults.json";
+/// When this file is next to the executable, the app runs in portable mode: the vaults,
+/// the settings and the registry are kept in the same dir, e.g. on a USB stick
+const PORTABLE_MARKER: &str = "rauthy.portable";
+/// The file of the only vault of older versions
+const DEFAULT_VAULT_FILE: &str = "Rauthy.bin";
+const DEFAULT_VAULT_ID: &str = "default";
+
+/// A vault known to the app. Each one has its own file, password and unlock methods.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultEntry {
+    pub id: String,
+    pub name: String,
+    /// The storage file, relative to the data dir (see [`data_dir`]). It's absolute for
+    /// vaults kept elsewhere, e.g. in an encrypted volume
+    pub path: PathBuf,
+}
+
+/// The named vaults and the one that's opened by the unlock commands. Like the settings,
+/// it isn't secret (the names are shown on the login screen), so it's stored as plain JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultRegistry {
+    pub vaults: Vec<VaultEntry>,
+    /// Id of the selected vault
+    pub active: String,
+}
+
+impl Default for VaultRegistry {
+    /// Only the vault of older versions, so it keeps opening with the same password
+    fn default() -> Self {
+        Self {
+            vaults: vec![VaultEntry {
+                id: DEFAULT_VAULT_ID.to_string(),
+                name: String::from("Default"),
+                path: PathBuf::from(DEFAULT_VAULT_FILE),
+            }],
+            active: DEFAULT_VAULT_ID.to_string(),
+        }
+    }
+}
+
+impl VaultRegistry {
+    /// Loads the registry from `dir`, falling back to the default vault when the file
+    /// doesn't exist or can't be parsed.
+    pub fn load(dir: &Path) -> Self {
+        match fs::read_to_string(dir.join(VAULTS_FILE)) {
+            Ok(content) => serde_json::from_str::<Self>(&content)
+                .ok()
+                .filter(|registry| registry.get(&registry.active).is_some())
+                .unwrap_or_else(|| {
+                    eprintln!("Invalid vaults file, using the default vault");
+                    VaultRegistry::default()
+                }),
+            Err(_) => VaultRegistry::default(),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), RauthyError> {
+        fs::create_dir_all(dir)?;
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|err| RauthyError::Io(err.to_string()))?;
+        fs::write(dir.join(VAULTS_FILE), content)?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&VaultEntry> {
+        self.vaults.iter().find(|vault| vault.id == id)
+    }
+
+    pub fn active(&self) -> &VaultEntry {
+        // `load` and `remove` never leave the registry without the active vault
+        self.get(&self.active).expect("The active vault isn't in the registry")
+    }
+
+    pub fn set_active(&mut self, id: &str) -> Result<(), RauthyError> {
+        self.get(id).ok_or(RauthyError::NotFound)?;
+        self.active = id.to_string();
+        Ok(())
+    }
+
+    /// Adds a vault with a new random id, in a file of its own in `directory` (the data dir
+    /// when it's `None`)
+    pub fn add(&mut self, name: &str, directory: Option<&Path>) -> Result<&VaultEntry, RauthyError> {
+        let id = new_vault_id();
+        let file_name = format!("{}.bin", id);
+        let path = match directory {
+            Some(directory) => directory.join(file_name),
+            None => PathBuf::from(file_name),
+        };
+        self.push(id, name, path)
+    }
+
+    /// Adds a vault file that already exists, e.g. created on another computer
+    pub fn attach(&mut self, name: &str, path: &Path) -> Result<&VaultEntry, RauthyError> {
+        if self.vaults.iter().any(|vault| vault.path == path) {
+            return Err(RauthyError::Duplicate);
+        }
+        self.push(new_vault_id(), name, path.to_path_buf())
+    }
+
+    pub fn set_path(&mut self, id: &str, path: PathBuf) -> Result<(), RauthyError> {
+        let vault = self
+            .vaults
+            .iter_mut()
+            .find(|vault| vault.id == id)
+            .ok_or(RauthyError::NotFound)?;
+        vault.path = path;
+        Ok(())
+    }
+
+    fn push(&mut self, id: String, name: &str, path: PathBuf) -> Result<&VaultEntry, RauthyError> {
+        let name = self.validate_name(name, None)?;
+        self.vaults.push(VaultEntry { id, name, path });
+        Ok(self.vaults.last().unwrap())
+    }
+
+    pub fn rename(&mut self, id: &str, name: &str) -> Result<(), RauthyError> {
+        let name = self.validate_name(name, Some(id))?;
+        let vault = self
+            .vaults
+            .iter_mut()
+            .find(|vault| vault.id == id)
+            .ok_or(RauthyError::NotFound)?;
+        vault.name = name;
+        Ok(())
+    }
+
+    /// Removes the vault from the registry (its files must be deleted by the caller).
+    /// When it was the active one, the first remaining vault becomes active, and when it
+    /// was the last one, the registry goes back to the default vault.
+    pub fn remove(&mut self, id: &str) -> Result<VaultEntry, RauthyError> {
+        let position = self
+            .vaults
+            .iter()
+            .position(|vault| vault.id == id)
+            .ok_or(RauthyError::NotFound)?;
+        let removed = self.vaults.remove(position);
+
+        if self.vaults.is_empty() {
+            *self = VaultRegistry::default();
+        } else if self.active == removed.id {
+            self.active = self.vaults[0].id.clone();
+        }
+        Ok(removed)
+    }
+
+    /// The names are shown in the vault switcher, so they can't be empty or repeated
+    fn validate_name(&self, name: &str, renamed_id: Option<&str>) -> Result<String, RauthyError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(RauthyError::InvalidVaultName("The vault name can't be empty"));
+        }
+        let taken = self
+            .vaults
+            .iter()
+            .any(|vault| Some(vault.id.as_str()) != renamed_id && vault.name.eq_ignore_ascii_case(name));
+        if taken {
+            return Err(RauthyError::InvalidVaultName("There's already a vault with this name"));
+        }
+        Ok(name.to_string())
+    }
+}
+
+fn new_vault_id() -> String {
+    let mut id = [0u8; 8];
+    rand::rng().fill_bytes(&mut id);
+    hex::encode(id)
+}
+
+/// Where the settings, the registry and the vaults with relative paths are kept: next to the
+/// executable in portable mode, the app local data dir otherwise.
+pub fn data_dir<R: Runtime>(app: &AppHandle<R>) -> PathBuf {
+    let portable_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .filter(|dir| dir.join(PORTABLE_MARKER).exists());
+
+    portable_dir.unwrap_or_else(|| {
+        app.path()
+            .app_local_data_dir()
+            .expect("Couldn't resolve app local data dir")
+    })
+}
+
+/// The vault file and the files kept next to it: its backup, its failed attempts and its
+/// audit log
+pub fn vault_files(vault_path: &Path) -> [PathBuf; 4] {
+    [
+        vault_path.with_extension("bin.backup"),
+        vault_path.to_path_buf(),
+        FailedAttempts::path(vault_path),
+        AuditLog::path(vault_path),
+    ]
+}
+
+/// Copies the files of the vault at `old_path` next to `new_path`. They're copied instead of
+/// renamed, so it also works across drives.
+pub fn copy_vault_files(old_path: &Path, new_path: &Path) -> Result<(), RauthyError> {
+    for (from, to) in vault_files(old_path).into_iter().zip(vault_files(new_path)) {
+        if from.exists() {
+            fs::copy(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn delete_vault_files(vault_path: &Path) -> Result<(), RauthyError> {
+    for path in vault_files(vault_path) {
+        if let Err(err) = fs::remove_file(&path) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(err.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_rename_remove() {
+        let mut registry = VaultRegistry::default();
+        let work = registry.add(" Work ", None).unwrap().clone();
+        assert_eq!(work.name, "Work");
+        assert_eq!(work.path, PathBuf::from(format!("{}.bin", work.id)));
+        assert!(registry.add("work", None).is_err());
+        assert!(registry.add("  ", None).is_err());
+
+        registry.rename(&work.id, "Client A").unwrap();
+        assert!(registry.rename(&work.id, "default").is_err());
+        assert_eq!(registry.get(&work.id).unwrap().name, "Client A");
+
+        registry.set_active(&work.id).unwrap();
+        assert_eq!(registry.remove(&work.id).unwrap().id, work.id);
+        assert_eq!(registry.active().id, DEFAULT_VAULT_ID);
+
+        // Removing the last vault goes back to a fresh default one
+        registry.remove(DEFAULT_VAULT_ID).unwrap();
+        assert_eq!(registry, VaultRegistry::default());
+    }
+
+    #[test]
+    fn test_vault_locations() {
+        let mut registry = VaultRegistry::default();
+        let usb = registry.add("USB", Some(Path::new("/media/usb"))).unwrap().clone();
+        assert_eq!(usb.path, Path::new("/media/usb").join(format!("{}.bin", usb.id)));
+
+        let attached = registry.attach("Laptop", Path::new("/media/usb/laptop.bin")).unwrap().clone();
+        assert_eq!(registry.attach("Again", &attached.path), Err(RauthyError::Duplicate));
+
+        registry.set_path(&usb.id, PathBuf::from("/secure/vault.bin")).unwrap();
+        assert_eq!(registry.get(&usb.id).unwrap().path, Path::new("/secure/vault.bin"));
+        assert_eq!(registry.set_path("missing", PathBuf::new()), Err(RauthyError::NotFound));
+    }
+
+    #[test]
+    fn test_load_falls_back_to_default() {
+        let dir = std::env::temp_dir().join(format!("rauthy-vaults-{}", std::process::id()));
+        assert_eq!(VaultRegistry::load(&dir), VaultRegistry::default());
+
+        let mut registry = VaultRegistry::default();
+        registry.add("Work", Some(Path::new("/media/usb"))).unwrap();
+        registry.save(&dir).unwrap();
+        assert_eq!(VaultRegistry::load(&dir), registry);
+
+        fs::write(dir.join(VAULTS_FILE), r#"{"vaults": [], "active": "default"}"#).unwrap();
+        assert_eq!(VaultRegistry::load(&dir), VaultRegistry::default());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_move_and_delete_vault_files() {
+        let dir = std::env::temp_dir().join(format!("rauthy-test-files-{}", rand::random::<u32>()));
+        let (old_dir, new_dir) = (dir.join("old"), dir.join("new"));
+        fs::create_dir_all(&old_dir).unwrap();
+        fs::create_dir_all(&new_dir).unwrap();
+        let (old_path, new_path) = (old_dir.join("Rauthy.bin"), new_dir.join("Rauthy.bin"));
+        for path in vault_files(&old_path) {
+            fs::write(path, b"content").unwrap();
+        }
+        assert!(vault_files(&old_path).iter().any(|path| path.extension().unwrap() == "audit"));
+
+        copy_vault_files(&old_path, &new_path).unwrap();
+        delete_vault_files(&old_path).unwrap();
+        assert!(vault_files(&old_path).iter().all(|path| !path.exists()));
+        assert!(vault_files(&new_path).iter().all(|path| fs::read(path).unwrap() == b"content"));
+
+        delete_vault_files(&new_path).unwrap();
+        assert!(vault_files(&new_path).iter().all(|path| !path.exists()));
+        // Missing files are already deleted
+        delete_vault_files(&new_path).unwrap();
+        fs::remove_dir_all(dir).ok();
+    }
+}
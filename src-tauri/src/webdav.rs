@@ -0,0 +1,265 @@
+use data_encoding::BASE64;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use zeroize::Zeroizing;
+
+use crate::error::RauthyError;
+use crate::sync::SyncBase;
+
+/// Seconds to wait for the server before giving up
+const REQUEST_TIMEOUT: u64 = 30;
+
+/// Where the vault is synced to, e.g. a file in Nextcloud:
+/// `https://cloud.example.com/remote.php/dav/files/user/Rauthy.bin`.
+///
+/// It's kept in the encrypted payload of the vault, as it holds the password of the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebDavConfig {
+    pub url: String,
+    pub username: String,
+    pub password: Zeroizing<String>,
+}
+
+impl WebDavConfig {
+    /// The credentials are sent with every request, so plain HTTP is only accepted for
+    /// servers on this computer
+    pub fn validate(&self) -> Result<(), RauthyError> {
+        let url = Url::parse(&self.url).map_err(|_| RauthyError::Network("Invalid WebDAV URL".to_string()))?;
+        let loopback = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+        match url.scheme() {
+            "https" => Ok(()),
+            "http" if loopback => Ok(()),
+            _ => Err(RauthyError::Network("The WebDAV URL must use https".to_string())),
+        }
+    }
+}
+
+/// What the frontend gets: everything but the password
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebDavStatus {
+    pub url: String,
+    pub username: String,
+}
+
+impl From<&WebDavConfig> for WebDavStatus {
+    fn from(config: &WebDavConfig) -> Self {
+        Self {
+            url: config.url.clone(),
+            username: config.username.clone(),
+        }
+    }
+}
+
+/// The remote file as it was last downloaded or uploaded by this device
+#[derive(Debug, Clone)]
+pub struct WebDavState {
+    /// `None` when the server doesn't send ETags
+    pub etag: Option<String>,
+    pub base: SyncBase,
+}
+
+pub enum Fetched {
+    /// The file didn't change since the ETag sent in `If-None-Match`
+    NotModified,
+    /// The file doesn't exist yet
+    Missing,
+    Found { content: Vec<u8>, etag: Option<String> },
+}
+
+/// Condition for a PUT, so changes uploaded by another device are never overwritten
+pub enum Precondition {
+    /// The file must still have this ETag (`If-Match`)
+    Match(String),
+    /// The file must not exist (`If-None-Match: *`)
+    Missing,
+    /// The server doesn't send ETags
+    None,
+}
+
+pub enum Stored {
+    Stored { etag: Option<String> },
+    /// Another device uploaded the file since it was downloaded: it must be merged again
+    Conflict,
+}
+
+pub struct WebDavClient<'a> {
+    config: &'a WebDavConfig,
+}
+
+impl<'a> WebDavClient<'a> {
+    pub fn new(config: &'a WebDavConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn get(&self, etag: Option<&str>) -> Result<Fetched, RauthyError> {
+        let mut request = self.authorized(minreq::get(self.config.url.as_str()));
+        if let Some(etag) = etag {
+            request = request.with_header("If-None-Match", etag);
+        }
+        let response = request.send().map_err(|err| RauthyError::Network(err.to_string()))?;
+
+        match response.status_code {
+            200 => Ok(Fetched::Found {
+                etag: response.headers.get("etag").cloned(),
+                content: response.as_bytes().to_vec(),
+            }),
+            304 => Ok(Fetched::NotModified),
+            404 => Ok(Fetched::Missing),
+            status => Err(status_error(status)),
+        }
+    }
+
+    pub fn put(&self, content: &[u8], precondition: Precondition) -> Result<Stored, RauthyError> {
+        let mut request = self
+            .authorized(minreq::put(self.config.url.as_str()))
+            .with_header("Content-Type", "application/octet-stream")
+            .with_body(content.to_vec());
+        request = match precondition {
+            Precondition::Match(etag) => request.with_header("If-Match", etag),
+            Precondition::Missing => request.with_header("If-None-Match", "*"),
+            Precondition::None => request,
+        };
+        let response = request.send().map_err(|err| RauthyError::Network(err.to_string()))?;
+
+        match response.status_code {
+            200 | 201 | 204 => Ok(Stored::Stored {
+                etag: response.headers.get("etag").cloned(),
+            }),
+            412 => Ok(Stored::Conflict),
+            status => Err(status_error(status)),
+        }
+    }
+
+    fn authorized(&self, request: minreq::Request) -> minreq::Request {
+        let credentials = Zeroizing::new(format!("{}:{}", self.config.username, self.config.password.as_str()));
+        let header = Zeroizing::new(format!("Basic {}", BASE64.encode(credentials.as_bytes())));
+        request
+            .with_header("Authorization", header.as_str())
+            .with_timeout(REQUEST_TIMEOUT)
+    }
+}
+
+fn status_error(status: i32) -> RauthyError {
+    match status {
+        401 | 403 => RauthyError::Network("The WebDAV server refused the username or password".to_string()),
+        status => RauthyError::Network(format!("The WebDAV server answered with status {}", status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// A WebDAV stand-in that stores a single file, with its version as the ETag
+    fn spawn_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/Rauthy.bin", listener.local_addr().unwrap());
+        // The content and its version
+        let mut file: Option<(Vec<u8>, u32)> = None;
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_once(':').unwrap();
+                    headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+                }
+                let header = |name: &str| headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone());
+                let mut body = vec![0u8; header("content-length").map_or(0, |len| len.parse().unwrap())];
+                reader.read_exact(&mut body).unwrap();
+
+                let etag = file.as_ref().map(|(_, version)| format!("\"{}\"", version));
+                let (status, response_etag, response_body) = if header("authorization").is_none() {
+                    ("401 Unauthorized", None, Vec::new())
+                } else if request_line.starts_with("GET") {
+                    match file.as_ref() {
+                        None => ("404 Not Found", None, Vec::new()),
+                        Some(_) if header("if-none-match") == etag => ("304 Not Modified", etag, Vec::new()),
+                        Some((content, _)) => ("200 OK", etag, content.clone()),
+                    }
+                } else {
+                    let allowed = match (header("if-match"), header("if-none-match")) {
+                        (Some(expected), _) => Some(expected) == etag,
+                        (_, Some(_)) => etag.is_none(),
+                        _ => true,
+                    };
+                    if allowed {
+                        let version = file.as_ref().map_or(1, |(_, version)| version + 1);
+                        file = Some((body, version));
+                        ("201 Created", Some(format!("\"{}\"", version)), Vec::new())
+                    } else {
+                        ("412 Precondition Failed", None, Vec::new())
+                    }
+                };
+
+                let etag_header = response_etag.map(|etag| format!("ETag: {}\r\n", etag)).unwrap_or_default();
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    etag_header,
+                    response_body.len()
+                )
+                .unwrap();
+                stream.write_all(&response_body).unwrap();
+            }
+        });
+        url
+    }
+
+    fn config(url: String) -> WebDavConfig {
+        WebDavConfig {
+            url,
+            username: String::from("user"),
+            password: Zeroizing::new(String::from("password")),
+        }
+    }
+
+    #[test]
+    fn test_optimistic_concurrency() {
+        let config = config(spawn_server());
+        let client = WebDavClient::new(&config);
+        assert!(matches!(client.get(None).unwrap(), Fetched::Missing));
+
+        let Stored::Stored { etag: Some(first) } = client.put(b"first", Precondition::Missing).unwrap() else {
+            panic!("The file wasn't stored");
+        };
+        assert!(matches!(client.put(b"again", Precondition::Missing).unwrap(), Stored::Conflict));
+        assert!(matches!(client.get(Some(&first)).unwrap(), Fetched::NotModified));
+
+        // Another device uploads a new version, so the old ETag doesn't match anymore
+        let Stored::Stored { etag: Some(second) } = client.put(b"second", Precondition::Match(first.clone())).unwrap() else {
+            panic!("The file wasn't stored");
+        };
+        assert!(matches!(client.put(b"stale", Precondition::Match(first.clone())).unwrap(), Stored::Conflict));
+        match client.get(Some(&first)).unwrap() {
+            Fetched::Found { content, etag } => {
+                assert_eq!(content, b"second");
+                assert_eq!(etag, Some(second));
+            }
+            _ => panic!("The new version wasn't downloaded"),
+        }
+    }
+
+    #[test]
+    fn test_validate_url() {
+        assert!(config(String::from("https://cloud.example.com/remote.php/dav/files/user/Rauthy.bin")).validate().is_ok());
+        assert!(config(String::from("http://127.0.0.1:8080/Rauthy.bin")).validate().is_ok());
+        assert!(config(String::from("http://cloud.example.com/Rauthy.bin")).validate().is_err());
+        assert!(config(String::from("not a url")).validate().is_err());
+    }
+}
@@ -64,6 +64,66 @@ export class AppComponent {
                 icon: 'pi pi-key',
                 command: () => this.emitMenuAction('changePassword')
             },
+            {
+                label: this.translate.translate('Split Recovery Key'),
+                icon: 'pi pi-users',
+                command: () => this.emitMenuAction('splitRecoveryKey')
+            },
+            {
+                label: this.translate.translate('Rename Vault'),
+                icon: 'pi pi-pencil',
+                command: () => this.emitMenuAction('renameVault')
+            },
+            {
+                label: this.translate.translate('Move Vault'),
+                icon: 'pi pi-folder-open',
+                command: () => this.emitMenuAction('moveVault')
+            },
+            {
+                label: this.translate.translate('Delete Vault'),
+                icon: 'pi pi-trash',
+                command: () => this.emitMenuAction('deleteVault')
+            },
+            {
+                label: this.translate.translate('Duress Password'),
+                icon: 'pi pi-eye-slash',
+                command: () => this.emitMenuAction('duressPassword')
+            },
+            {
+                label: this.translate.translate('WebDAV Sync'),
+                icon: 'pi pi-cloud',
+                command: () => this.emitMenuAction('webDavSync')
+            },
+            {
+                label: this.translate.translate('Team Members'),
+                icon: 'pi pi-users',
+                command: () => this.emitMenuAction('teamMembers')
+            },
+            {
+                label: this.translate.translate('Stale Accounts'),
+                icon: 'pi pi-clock',
+                command: () => this.emitMenuAction('staleServices')
+            },
+            {
+                label: this.translate.translate('Recently Deleted'),
+                icon: 'pi pi-trash',
+                command: () => this.emitMenuAction('recentlyDeleted')
+            },
+            {
+                label: this.translate.translate('Audit Log'),
+                icon: 'pi pi-history',
+                command: () => this.emitMenuAction('auditLog')
+            },
+            {
+                label: this.translate.translate('Send to Another Device'),
+                icon: 'pi pi-send',
+                command: () => this.emitMenuAction('sendTransfer')
+            },
+            {
+                label: this.translate.translate('Receive from Another Device'),
+                icon: 'pi pi-download',
+                command: () => this.emitMenuAction('receiveTransfer')
+            },
             {
                 separator: true
             },
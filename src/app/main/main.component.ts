@@ -1,4 +1,4 @@
-import { Component, inject, OnInit, signal } from '@angular/core';
+import { Component, effect, inject, OnInit, signal, untracked } from '@angular/core';
 import { FormBuilder, FormsModule, ReactiveFormsModule, Validators } from '@angular/forms';
 import { DialogModule } from 'primeng/dialog';
 import { ButtonModule } from 'primeng/button';
@@ -26,9 +26,17 @@ import { writeTextFile, readTextFile } from '@tauri-apps/plugin-fs';
 import { save, open } from '@tauri-apps/plugin-dialog';
 
 import { TotpService } from '../services/totp.service';
-import { Service } from '../models/service.model';
+import { CopiedToken, Service, ServiceEdit, ServiceOrder, ServiceVersion, SortMode, StaleService } from '../models/service.model';
 import { TotpToken } from '../models/token.model';
+import { errorMessage } from '../models/error.model';
+import { RecoveryShare } from '../models/recovery-share.model';
+import { VaultRegistry, WebDavStatus } from '../models/vault.model';
+import { TransferOffer } from '../models/transfer.model';
+import { Identity, TeamMember } from '../models/team.model';
+import { AuditEvent, AuditTrail } from '../models/audit.model';
+import { SearchResults } from '../models/search.model';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { LocalStorageService } from '../services/local-storage.service';
 import { ServiceListComponent } from './service-list/service-list.component';
 import { isMobile } from '../utils/platform';
@@ -78,6 +86,62 @@ export class MainComponent implements OnInit {
     showEditDialog = signal(false);
     showDeleteDialog = signal(false);
     showPasswordChangeDialog = signal(false);
+    unlockWaitSeconds = signal(0);
+    showRecoveryUnlock = signal(false);
+    showSplitRecoveryDialog = signal(false);
+    showDuressPasswordDialog = signal(false);
+    showCreateVaultDialog = signal(false);
+    showRenameVaultDialog = signal(false);
+    showDeleteVaultDialog = signal(false);
+    showExportDialog = signal(false);
+    showWebDavDialog = signal(false);
+    showDownloadVaultDialog = signal(false);
+    webDavStatus = signal<WebDavStatus | null>(null);
+    showSendTransferDialog = signal(false);
+    showReceiveTransferDialog = signal(false);
+    // Services chosen to be sent to another device
+    transferServiceIds = signal(new Set<string>());
+    transferOffer = signal<TransferOffer | null>(null);
+    showTeamDialog = signal(false);
+    identity = signal<Identity | null>(null);
+    teamMembers = signal<TeamMember[]>([]);
+    // Whether the new vault is shared with a team, and opened with the identity of each member
+    newVaultForTeam = signal(false);
+    showAuditLogDialog = signal(false);
+    showHistoryDialog = signal(false);
+    showOrganizeDialog = signal(false);
+    showStaleDialog = signal(false);
+    staleMonths = 6;
+    staleServices = signal<StaleService[]>([]);
+    serviceOrder = signal<ServiceOrder>({ sort: 'manual', ids: [] });
+    searchResults = signal<SearchResults>({ query: '', matches: [] });
+    private lastSearch = '';
+    // The order is loaded again whenever the services are
+    private orderLoader = effect(() => {
+        this.totpItems();
+        untracked(() => this.loadServiceOrder());
+    });
+    // The services shown when the organize dialog was opened
+    organizeIds = signal<string[]>([]);
+    organizeTag = '';
+    organizeFolder = '';
+    showDeletedDialog = signal(false);
+    // The service whose previous versions are shown, newest first
+    historyService = signal<Service | null>(null);
+    serviceVersions = signal<ServiceVersion[]>([]);
+    deletedServices = signal<ServiceVersion[]>([]);
+    auditTrail = signal<AuditTrail | null>(null);
+    // Code shown on both devices once they're connected
+    transferSas = signal<string | null>(null);
+    vaults = signal<VaultRegistry | null>(null);
+    // Vault chosen in the login screen. It becomes the active one when it's unlocked
+    selectedVaultId = signal<string>('');
+    // Dir chosen for a new vault. The data dir when it's undefined
+    newVaultLocation = signal<string | undefined>(undefined);
+    recoveryShares = signal<RecoveryShare[]>([]);
+    recoveryKey = signal<string | null>(null);
+    // Path of the key file, for vaults that require one besides the password
+    keyFile = signal<string | undefined>(undefined);
 
     selectedService?: Service;
     serviceToDelete?: Service;
@@ -85,6 +149,8 @@ export class MainComponent implements OnInit {
     isMobile = isMobile();
 
     encryptedPassword = "";
+    private countdownSubscription?: Subscription;
+    private unlockWaitTimer?: ReturnType<typeof setInterval>;
 
     constructor(
         private totpService: TotpService,
@@ -101,7 +167,7 @@ export class MainComponent implements OnInit {
             const action = event.detail.action;
             switch (action) {
                 case 'export':
-                    this.exportServices();
+                    this.showExportDialog.set(true);
                     break;
                 case 'import':
                     this.importServices();
@@ -109,12 +175,102 @@ export class MainComponent implements OnInit {
                 case 'changePassword':
                     this.changePassword();
                     break;
+                case 'splitRecoveryKey':
+                    this.showSplitRecoveryDialog.set(true);
+                    break;
+                case 'duressPassword':
+                    this.showDuressPasswordDialog.set(true);
+                    break;
+                case 'renameVault':
+                    this.showRenameVaultDialog.set(true);
+                    break;
+                case 'deleteVault':
+                    this.showDeleteVaultDialog.set(true);
+                    break;
+                case 'moveVault':
+                    this.moveVault();
+                    break;
+                case 'webDavSync':
+                    this.openWebDavDialog();
+                    break;
+                case 'sendTransfer':
+                    this.transferServiceIds.set(new Set(this.totpItems().keys()));
+                    this.showSendTransferDialog.set(true);
+                    break;
+                case 'receiveTransfer':
+                    this.showReceiveTransferDialog.set(true);
+                    break;
+                case 'teamMembers':
+                    this.openTeamDialog();
+                    break;
+                case 'auditLog':
+                    this.openAuditLog();
+                    break;
+                case 'recentlyDeleted':
+                    this.openDeletedServices();
+                    break;
+                case 'staleServices':
+                    this.loadStaleServices();
+                    this.showStaleDialog.set(true);
+                    break;
                 case 'logout':
                     this.logout();
                     break;
             }
         });
 
+        // The backend locks the vault after some idle time or when the system sleeps/locks
+        listen<{reason: string}>('locked', event => {
+            if (event.payload.reason !== 'manual') {
+                this.performLogout(false);
+            }
+        });
+        this.reportUserActivity();
+        this.loadVaults();
+
+        // Changes made on other devices (e.g. through a synced folder) were merged into the open vault
+        listen<{report: {added: string[], updated: string[], removed: string[]}, services: object}>('vault-synced', event => {
+            const { report, services } = event.payload;
+            this.totpItems.set(new Map(Object.entries(services)));
+            this.messageService.add({
+                severity: 'info',
+                summary: this.translate.translate('Merged the changes made on other devices'),
+                detail: `+${report.added.length} ~${report.updated.length} -${report.removed.length}`
+            });
+        });
+        window.addEventListener('focus', () => {
+            if (this.totpItems().size > 0) {
+                this.totpService.syncVault().subscribe({ error: error => console.error('Error syncing the vault:', error) });
+            }
+        });
+
+        // Transfers to or from another device on the local network
+        listen<{sas: string}>('transfer-paired', event => {
+            this.transferSas.set(event.payload.sas);
+        });
+        listen<{count: number, error: unknown | null, services: object | null, skipped: string[], renamed: string[]}>('transfer-finished', event => {
+            const { count, error, services, skipped, renamed } = event.payload;
+            this.closeTransferDialogs();
+            if (services) {
+                this.totpItems.set(new Map(Object.entries(services)));
+            }
+            this.messageService.add(error
+                ? { severity: 'error', summary: this.translate.translate('The transfer failed'), detail: errorMessage(error) }
+                : { severity: 'success', summary: this.translate.translate('Services transferred'), detail: `${count}` });
+            // Services whose id was already in the vault are never replaced
+            if (skipped.length > 0) {
+                this.messageService.add({ severity: 'info', summary: this.translate.translate('Already in the vault'), detail: skipped.join(', ') });
+            }
+            if (renamed.length > 0) {
+                this.messageService.add({ severity: 'warn', summary: this.translate.translate('Added next to a service with the same name'), detail: renamed.join(', ') });
+            }
+        });
+
+        // Sent after each failed unlock, with how long we must wait before the next attempt
+        listen<{failedAttempts: number, waitSeconds: number}>('unlock-throttled', event => {
+            this.startUnlockWait(event.payload.waitSeconds);
+        });
+
         const hasBiometrics = await checkStatus();
         if (hasBiometrics.isAvailable) {
             this.isBiometricAble = true;
@@ -132,14 +288,21 @@ export class MainComponent implements OnInit {
     }
 
     async onSubmit(internal: boolean = false) {
-        if (this.form.valid || internal) {
+        if ((this.form.valid || internal) && this.unlockWaitSeconds() <= 0) {
             this.loadingServices.set(true);
-            const subscription = this.totpService.setupStorageKeys(this.form.value.password as string).subscribe({
+            const password = this.form.value.password as string;
+            const vaultId = this.selectedVaultId();
+            const unlock = vaultId && vaultId !== this.vaults()?.active
+                ? this.totpService.openVault(vaultId, password, this.keyFile())
+                : this.totpService.setupStorageKeys(password, this.keyFile());
+            const subscription = unlock.subscribe({
                 next: services => {
                     this.loadingServices.set(false);
                     subscription.unsubscribe();
+                    this.loadVaults();
                     this.totpItems.set(services);
                     this.emitAuthenticationState(true);
+                    this.showPendingRecoveryKey();
                     if (services.size === 0) {
                         this.showDialog.set(true);
                     } else {
@@ -157,7 +320,7 @@ export class MainComponent implements OnInit {
                     this.emitAuthenticationState(false);
                     this.messageService.add({
                         summary: this.translate.translate("Error trying to open the services file"),
-                        detail: this.translate.translate("Couldn't open the services file: ") + error,
+                        detail: this.translate.translate("Couldn't open the services file: ") + errorMessage(error),
                         severity: 'error',
                     })
                 }
@@ -165,6 +328,587 @@ export class MainComponent implements OnInit {
         }
     }
 
+    // Accepts the recovery key, or one share of it per line
+    unlockWithRecoveryKey(recoveryKey: string, newPassword: string) {
+        this.loadingServices.set(true);
+        const shares = recoveryKey.split('\n').map(line => line.trim()).filter(line => line.length > 0);
+        const unlock = shares.length > 1
+            ? this.totpService.unlockWithRecoveryShares(shares, newPassword, this.keyFile())
+            : this.totpService.unlockWithRecoveryKey(recoveryKey, newPassword, this.keyFile());
+        unlock.subscribe({
+            next: services => {
+                this.loadingServices.set(false);
+                this.showRecoveryUnlock.set(false);
+                this.totpItems.set(services);
+                this.emitAuthenticationState(true);
+                this.showTokens();
+            },
+            error: error => {
+                this.loadingServices.set(false);
+                this.messageService.add({
+                    summary: this.translate.translate("Error trying to open the services file"),
+                    detail: this.translate.translate("Couldn't open the services file: ") + errorMessage(error),
+                    severity: 'error',
+                });
+            }
+        });
+    }
+
+    splitRecoveryKey(password: string, count: number, threshold: number) {
+        this.totpService.splitRecoveryKey(password, count, threshold).subscribe({
+            next: shares => this.recoveryShares.set(shares),
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not split the recovery key'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    loadVaults() {
+        this.totpService.listVaults().subscribe(vaults => {
+            this.vaults.set(vaults);
+            this.selectedVaultId.set(vaults.active);
+        });
+    }
+
+    activeVaultName(): string {
+        const vaults = this.vaults();
+        return vaults?.vaults.find(vault => vault.id === vaults.active)?.name ?? '';
+    }
+
+    createVault(name: string, password: string) {
+        const request = this.newVaultForTeam()
+            ? this.totpService.createTeamVault(name, password, this.newVaultLocation())
+            : this.totpService.createVault(name, password, this.keyFile(), this.newVaultLocation());
+        request.subscribe({
+            next: services => {
+                this.showCreateVaultDialog.set(false);
+                this.newVaultLocation.set(undefined);
+                this.newVaultForTeam.set(false);
+                this.loadVaults();
+                this.totpItems.set(services);
+                this.emitAuthenticationState(true);
+                this.showPendingRecoveryKey();
+                this.showDialog.set(true);
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not create the vault'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    async chooseVaultLocation() {
+        const directory = await open({ multiple: false, directory: true });
+        this.newVaultLocation.set(directory ?? undefined);
+    }
+
+    // Adds a vault file kept elsewhere (e.g. on a USB stick) and selects it in the login screen
+    async addVaultFile() {
+        const path = await open({ multiple: false, directory: false });
+        if (!path) {
+            return;
+        }
+        const name = path.split(/[\\/]/).pop()?.replace(/\.bin$/, '') ?? path;
+        this.totpService.addVaultFile(name, path, this.form.value.password ?? '').subscribe({
+            next: vaults => {
+                this.vaults.set(vaults);
+                this.selectedVaultId.set(vaults.vaults[vaults.vaults.length - 1].id);
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not add the vault'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    async moveVault() {
+        const directory = await open({ multiple: false, directory: true });
+        const id = this.vaults()?.active;
+        if (!directory || !id) {
+            return;
+        }
+        this.totpService.moveVault(id, directory).subscribe({
+            next: vaults => this.vaults.set(vaults),
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not move the vault'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    renameVault(name: string) {
+        const id = this.vaults()?.active ?? '';
+        this.totpService.renameVault(id, name).subscribe({
+            next: () => {
+                this.showRenameVaultDialog.set(false);
+                this.loadVaults();
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not rename the vault'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    // Deletes the open vault. The backend closes it, so we go back to the login screen
+    deleteVault(password: string) {
+        const id = this.vaults()?.active ?? '';
+        this.totpService.deleteVault(id, password, this.keyFile()).subscribe({
+            next: () => {
+                this.showDeleteVaultDialog.set(false);
+                this.loadVaults();
+                this.performLogout();
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not delete the vault'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    openWebDavDialog() {
+        this.totpService.getWebDavSync().subscribe(status => {
+            this.webDavStatus.set(status);
+            this.showWebDavDialog.set(true);
+        });
+    }
+
+    saveWebDavSync(url: string, username: string, password: string, vaultPassword: string) {
+        this.totpService.setWebDavSync(url, username, password, vaultPassword).subscribe({
+            next: () => {
+                this.showWebDavDialog.set(false);
+                this.messageService.add({
+                    severity: 'success',
+                    summary: this.translate.translate('The vault is synced with the WebDAV server'),
+                });
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not sync with the WebDAV server'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    disableWebDavSync(vaultPassword: string) {
+        this.totpService.disableWebDavSync(vaultPassword).subscribe({
+            next: () => {
+                this.webDavStatus.set(null);
+                this.showWebDavDialog.set(false);
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not stop syncing with the WebDAV server'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    // Adds a vault synced by another device and selects it in the login screen
+    downloadWebDavVault(name: string, url: string, username: string, password: string) {
+        this.totpService.downloadWebDavVault(name, url, username, password).subscribe({
+            next: vaults => {
+                this.showDownloadVaultDialog.set(false);
+                this.vaults.set(vaults);
+                this.selectedVaultId.set(vaults.vaults[vaults.vaults.length - 1].id);
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not download the vault'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    toggleTransferService(id: string) {
+        const ids = new Set(this.transferServiceIds());
+        if (!ids.delete(id)) {
+            ids.add(id);
+        }
+        this.transferServiceIds.set(ids);
+    }
+
+    startTransfer(password: string) {
+        this.totpService.startTransfer([...this.transferServiceIds()], password).subscribe({
+            next: offer => this.transferOffer.set(offer),
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not start the transfer'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    // The services are only sent once the user confirms both devices show the same code, with the password
+    confirmTransfer(confirmed: boolean, password?: string) {
+        this.totpService.confirmTransfer(confirmed, password).subscribe({
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not send the services'),
+                detail: errorMessage(error)
+            })
+        });
+        if (!confirmed) {
+            this.closeTransferDialogs();
+        }
+    }
+
+    async scanTransferCode() {
+        try {
+            const scanned = await scan({ windowed: true, formats: [Format.QRCode] });
+            if (scanned.content) {
+                this.receiveTransfer(scanned.content);
+            }
+        } catch (err) {
+            this.messageService.add({
+                summary: this.translate.translate("Camera access denied"),
+                detail: this.translate.translate("Couldn't open the device camera. You'll need to authorize the app manually") + err,
+                severity: 'error',
+            });
+        }
+    }
+
+    receiveTransfer(uri: string) {
+        this.totpService.receiveTransfer(uri).subscribe({
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not connect to the other device'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    closeTransferDialogs() {
+        // Cancels the pending transfer, if any
+        if (this.transferOffer()) {
+            this.totpService.confirmTransfer(false).subscribe({ error: () => {} });
+        }
+        this.showSendTransferDialog.set(false);
+        this.showReceiveTransferDialog.set(false);
+        this.transferOffer.set(null);
+        this.transferSas.set(null);
+    }
+
+    transferQrImage(offer: TransferOffer): string {
+        return 'data:image/svg+xml;base64,' + btoa(offer.qrCode);
+    }
+
+    openTeamDialog() {
+        this.totpService.getIdentity().subscribe(identity => this.identity.set(identity));
+        this.totpService.listMembers().subscribe({
+            next: members => {
+                this.teamMembers.set(members);
+                this.showTeamDialog.set(true);
+            },
+            error: error => console.error('Error listing the team members:', error)
+        });
+    }
+
+    createIdentity(name: string, password: string) {
+        this.totpService.createIdentity(name, password).subscribe({
+            next: identity => this.identity.set(identity),
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not create the identity'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    addMember(name: string, publicKey: string, password: string) {
+        this.totpService.addMember(name, publicKey, password).subscribe({
+            next: members => this.teamMembers.set(members),
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not add the member'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    // The vault is re-keyed without the member, so it gets a new recovery key
+    removeMember(member: TeamMember, password: string) {
+        this.totpService.removeMember(member.publicKey, password).subscribe({
+            next: members => {
+                this.teamMembers.set(members);
+                this.showPendingRecoveryKey();
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not remove the member'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    onSearch(query: string) {
+        this.lastSearch = query;
+        this.totpService.searchServices(query).subscribe({
+            next: matches => {
+                // Answers to older searches can arrive late
+                if (query === this.lastSearch) {
+                    this.searchResults.set({ query, matches });
+                }
+            },
+            error: error => console.error('Error searching the services:', error)
+        });
+    }
+
+    loadStaleServices() {
+        this.totpService.listStaleServices(this.staleMonths).subscribe({
+            next: stale => this.staleServices.set(stale),
+            error: error => console.error('Error listing the stale services:', error)
+        });
+    }
+
+    deleteStaleService(stale: StaleService) {
+        const service = this.totpItems().get(stale.id);
+        if (service) {
+            this.showStaleDialog.set(false);
+            this.deleteService(service);
+        }
+    }
+
+    staleDate(stale: StaleService): string {
+        return DateTime.fromSeconds(stale.lastUsed || stale.created).toLocaleString(DateTime.DATE_MED);
+    }
+
+    loadServiceOrder() {
+        this.totpService.listServiceOrder().subscribe({
+            next: order => this.serviceOrder.set(order),
+            error: error => console.error('Error loading the order of the services:', error)
+        });
+    }
+
+    onSortChange(sort: SortMode) {
+        this.totpService.setSortMode(sort).subscribe({
+            next: order => this.serviceOrder.set(order),
+            error: error => console.error('Error saving the sort mode:', error)
+        });
+    }
+
+    onReorder(serviceIds: string[]) {
+        // Shown right away, before the backend saves it
+        this.serviceOrder.set({ sort: 'manual', ids: serviceIds });
+        this.totpService.reorderServices(serviceIds).subscribe({
+            next: order => this.serviceOrder.set(order),
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not reorder the services'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    openOrganize(serviceIds: string[]) {
+        this.organizeIds.set(serviceIds);
+        this.organizeTag = '';
+        this.organizeFolder = '';
+        this.showOrganizeDialog.set(true);
+    }
+
+    tagShownServices(add: boolean) {
+        const tags = [this.organizeTag.trim()];
+        const request = add
+            ? this.totpService.tagServices(this.organizeIds(), tags)
+            : this.totpService.tagServices(this.organizeIds(), [], tags);
+        request.subscribe({
+            next: services => this.onServicesOrganized(services),
+            error: error => this.onOrganizeError(error)
+        });
+    }
+
+    moveShownServices() {
+        this.totpService.moveServicesToFolder(this.organizeIds(), this.organizeFolder.trim() || null).subscribe({
+            next: services => this.onServicesOrganized(services),
+            error: error => this.onOrganizeError(error)
+        });
+    }
+
+    private onServicesOrganized(services: Map<string, Service>) {
+        this.totpItems.set(services);
+        this.showOrganizeDialog.set(false);
+        this.showTokens();
+    }
+
+    private onOrganizeError(error: unknown) {
+        this.messageService.add({
+            severity: 'error',
+            summary: this.translate.translate('Could not organize the services'),
+            detail: errorMessage(error)
+        });
+    }
+
+    openServiceHistory(service: Service) {
+        this.totpService.getServiceHistory(service.id).subscribe({
+            next: versions => {
+                this.historyService.set(service);
+                this.serviceVersions.set(versions);
+                this.showHistoryDialog.set(true);
+            },
+            error: error => console.error('Error reading the service history:', error)
+        });
+    }
+
+    // The current version is kept in the history, so restoring can be undone too
+    restoreServiceVersion(index: number) {
+        const service = this.historyService();
+        if (!service) {
+            return;
+        }
+        this.totpService.restoreServiceVersion(service.id, index).subscribe({
+            next: services => {
+                this.totpItems.set(services);
+                this.showHistoryDialog.set(false);
+                this.showTokens();
+                this.messageService.add({
+                    severity: 'success',
+                    summary: this.translate.translate('The previous version was restored')
+                });
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not restore the version'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    openDeletedServices() {
+        this.totpService.listDeletedServices().subscribe({
+            next: deleted => {
+                // Newest first
+                this.deletedServices.set([...deleted].reverse());
+                this.showDeletedDialog.set(true);
+            },
+            error: error => console.error('Error listing the deleted services:', error)
+        });
+    }
+
+    undoDeleteService(serviceId: string) {
+        this.totpService.undoDeleteService(serviceId).subscribe({
+            next: services => {
+                this.totpItems.set(services);
+                this.showDeletedDialog.set(false);
+                this.showTokens();
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not restore the service'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    versionDate(version: ServiceVersion): string {
+        return DateTime.fromSeconds(version.replaced).toLocaleString(DateTime.DATETIME_MED);
+    }
+
+    openAuditLog() {
+        this.totpService.getAuditLog().subscribe({
+            next: trail => {
+                // Newest first
+                this.auditTrail.set({ ...trail, events: [...trail.events].reverse() });
+                this.showAuditLogDialog.set(true);
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not read the audit log'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    auditEventDate(event: AuditEvent): string {
+        return DateTime.fromSeconds(event.timestamp).toLocaleString(DateTime.DATETIME_MED_WITH_SECONDS);
+    }
+
+    exportAuditLog() {
+        this.totpService.exportAuditLog().subscribe({
+            next: async content => {
+                const timestamp = DateTime.now().toFormat('yyyy-MM-dd_HH-mm-ss');
+                const filePath = await save({
+                    title: this.translate.translate('Save Audit Log'),
+                    defaultPath: `rauthy-audit-${timestamp}.json`,
+                    filters: [{ name: 'JSON Files', extensions: ['json'] }]
+                });
+                if (filePath) {
+                    await writeTextFile(filePath, content);
+                }
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not export the audit log'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    copyPublicKey(publicKey: string) {
+        this.clipboard.copy(publicKey);
+        this.snackbar.open(this.translate.translate("Public key copied to clipboard"), "", {
+            duration: 4000
+        });
+    }
+
+    // An empty duress password removes the decoy services
+    saveDuressPassword(password: string, duressPassword: string) {
+        const request = duressPassword
+            ? this.totpService.setDuressPassword(password, duressPassword)
+            : this.totpService.removeDuressPassword(password);
+        request.subscribe({
+            next: () => {
+                this.showDuressPasswordDialog.set(false);
+                this.messageService.add({
+                    severity: 'success',
+                    summary: this.translate.translate('Duress password saved'),
+                });
+            },
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not save the duress password'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    closeSplitRecoveryDialog() {
+        this.recoveryShares.set([]);
+        this.showSplitRecoveryDialog.set(false);
+    }
+
+    shareImage(share: RecoveryShare): string {
+        return 'data:image/svg+xml;base64,' + btoa(share.qrCode);
+    }
+
+    // New vaults (and the ones migrated from older versions) get a recovery key, shown only once
+    private showPendingRecoveryKey() {
+        this.totpService.takeRecoveryKey().subscribe(recoveryKey => {
+            this.recoveryKey.set(recoveryKey);
+        });
+    }
+
+    private startUnlockWait(seconds: number) {
+        clearInterval(this.unlockWaitTimer);
+        this.unlockWaitSeconds.set(seconds);
+        if (seconds <= 0) {
+            return;
+        }
+        this.unlockWaitTimer = setInterval(() => {
+            this.unlockWaitSeconds.update(remaining => remaining - 1);
+            if (this.unlockWaitSeconds() <= 0) {
+                clearInterval(this.unlockWaitTimer);
+            }
+        }, 1000);
+    }
+
     async onSubmitServiceUrl(serviceUrl: string) {
         this.addNewService(serviceUrl);
     }
@@ -214,49 +958,58 @@ export class MainComponent implements OnInit {
     }
 
     addNewService(url: string) {
-        const subscription = this.totpService.addService(url).subscribe(services => {
-            const oldItemsCount = this.totpItems().size;
-            this.totpItems.set(services);
-            this.showTokens();
-            if (services.size <= oldItemsCount) {
-                this.showDialog.set(true);
-                this.messageService.add({
-                    summary: this.translate.translate("Service format Error"),
-                    detail: this.translate.translate("Couldn't add this service!"),
-                    severity: 'error',
-                });
-            } else {
+        const subscription = this.totpService.addService(url).subscribe({
+            next: services => {
+                this.totpItems.set(services);
+                this.showTokens();
                 this.showDialog.set(false);
                 this.messageService.add({
                     summary: this.translate.translate("Service Added"),
                     detail: this.translate.translate("Service added successfully!"),
                     severity: 'success',
                 });
+                subscription.unsubscribe();
+            },
+            error: error => {
+                this.showDialog.set(true);
+                this.messageService.add({
+                    summary: this.translate.translate("Service format Error"),
+                    detail: this.translate.translate("Couldn't add this service!") + " " + errorMessage(error),
+                    severity: 'error',
+                });
+                subscription.unsubscribe();
             }
-            subscription.unsubscribe();
         });
     }
 
     showTokens() {
+        // Tokens that expire are pushed by the backend through the tokens-updated event,
+        // so we only need to fetch them once and keep the countdown running
         const subscription = this.totpService.getServicesTokens().subscribe(tokensMap => {
             subscription.unsubscribe();
             this.tokensMap = tokensMap;
-            this.calculateTokenDuration(null);
-            const intervalSubscription = interval(1000).subscribe(() => {
-                this.calculateTokenDuration(intervalSubscription);
+            this.calculateTokenDuration();
+            this.countdownSubscription?.unsubscribe();
+            this.countdownSubscription = interval(1000).subscribe(() => {
+                this.tokensMap = this.totpService.tokensContent;
+                this.calculateTokenDuration();
             });
         });
     }
 
-    copyToken(token: string) {
-        this.clipboard.copy(token);
+    copyToken(copied: CopiedToken) {
+        this.clipboard.copy(copied.token);
+        this.totpService.recordServiceUse(copied.serviceId).subscribe({
+            error: error => console.error('Error recording the use of the service:', error)
+        });
         this.snackbar.open(this.translate.translate("Token copied to clipboard"), "", {
             duration: 4000
         });
     }
 
-    async storePasswordWithBiometrics(event: Event) {
-        const password = this.form.value.password;
+    async storePasswordWithBiometrics(password: string) {
+        // A random secret that unlocks the vault, so the password itself is never stored
+        const biometricSecret = await invoke<string>('enable_biometric_unlock', { password });
         const options = {
             // Set true if you want the user to be able to authenticate using phone password
             allowDeviceCredential: false,
@@ -269,7 +1022,7 @@ export class MainComponent implements OnInit {
         const encryptedData = await invoke<{data: string}>('plugin:biometric|biometric_cipher', {
             reason: "Next time you will be able to login with your biometrics",
             ...options,
-            dataToEncrypt: password
+            dataToEncrypt: biometricSecret
         });
         
         // closes the dialog
@@ -314,33 +1067,30 @@ export class MainComponent implements OnInit {
                 this.emitAuthenticationState(false);
                 this.messageService.add({
                     summary: this.translate.translate("Error trying to open the services file"),
-                    detail: this.translate.translate("Couldn't open the services file: ") + error,
+                    detail: this.translate.translate("Couldn't open the services file: ") + errorMessage(error),
                     severity: 'error',
                 })
             }
         });
     }
 
-    private calculateTokenDuration(intervalSubscription: Subscription | null) {
-        let minDuration = Infinity;
+    private calculateTokenDuration() {
         const durations = new Map<string, number>();
         this.tokensMap.forEach((token, key) => {
             const duration = Math.round(DateTime.fromJSDate(token.nextStepTime).diffNow('seconds').as('seconds'));
-            minDuration = Math.min(minDuration, duration);
-            durations.set(key, duration);
+            durations.set(key, Math.max(duration, 0));
         });
         this.tokensDuration = durations;
-        if (minDuration < 0) {
-            this.showTokens();
-            intervalSubscription?.unsubscribe();
-        }
     }
 
-    onServiceEdit(event: {id: string, name: string, issuer: string}) {
+    onServiceEdit(event: ServiceEdit) {
         const service = this.totpItems().get(event.id);
         if (service) {
             service.name = event.name;
             service.issuer = event.issuer;
+            service.tags = event.tags;
+            service.folder = event.folder;
+            service.favorite = event.favorite;
             // Update the service in storage
             const subscription = this.totpService.updateService(service).subscribe({
                 next: () => {
@@ -354,7 +1104,7 @@ export class MainComponent implements OnInit {
                     this.messageService.add({
                         severity: 'error',
                         summary: this.translate.translate('Update Error'),
-                        detail: this.translate.translate('Could not update service: ') + error
+                        detail: this.translate.translate('Could not update service: ') + errorMessage(error)
                     });
                 }
             });
@@ -375,12 +1125,11 @@ export class MainComponent implements OnInit {
         if (this.serviceToDelete) {
             const subscription = this.totpService.deleteService(this.serviceToDelete.id).subscribe({
                 next: () => {
-                    this.serviceToDelete?.id ? this.totpItems().delete(this.serviceToDelete.id) : undefined;
-                    this.messageService.add({
-                        severity: 'success',
-                        summary: this.translate.translate('Service Deleted'),
-                        detail: this.translate.translate('Service deleted successfully!')
-                    });
+                    const deletedId = this.serviceToDelete?.id;
+                    deletedId ? this.totpItems().delete(deletedId) : undefined;
+                    this.snackbar.open(this.translate.translate("Service deleted successfully!"), this.translate.translate("Undo"), {
+                        duration: 8000
+                    }).onAction().subscribe(() => deletedId && this.undoDeleteService(deletedId));
                     this.showDeleteDialog.set(false);
                     this.serviceToDelete = undefined;
                     this.showTokens();
@@ -389,7 +1138,7 @@ export class MainComponent implements OnInit {
                     this.messageService.add({
                         severity: 'error',
                         summary: this.translate.translate('Delete Error'),
-                        detail: this.translate.translate('Could not delete service: ') + error
+                        detail: this.translate.translate('Could not delete service: ') + errorMessage(error)
                     });
                 }
             });
@@ -409,11 +1158,13 @@ export class MainComponent implements OnInit {
         this.showEditDialog.set(value)
     }
 
-    async exportServices() {
+    // The CSV file has every secret in plain text, so the password is asked again
+    async exportServices(password: string) {
         try {
-            const subscription = this.totpService.exportServicesCsv().subscribe({
+            const subscription = this.totpService.exportServicesCsv(password).subscribe({
                 next: async (csvContent) => {
                     subscription.unsubscribe();
+                    this.showExportDialog.set(false);
                     
                     // Generate filename with timestamp
                     const timestamp = DateTime.now().toFormat('yyyy-MM-dd_HH-mm-ss');
@@ -445,7 +1196,7 @@ export class MainComponent implements OnInit {
                     this.messageService.add({
                         severity: 'error',
                         summary: this.translate.translate('Export Error'),
-                        detail: this.translate.translate('Could not export services: ') + error
+                        detail: this.translate.translate('Could not export services: ') + errorMessage(error)
                     });
                 }
             });
@@ -453,7 +1204,7 @@ export class MainComponent implements OnInit {
             this.messageService.add({
                 severity: 'error',
                 summary: this.translate.translate('Export Error'),
-                detail: this.translate.translate('Could not export services: ') + error
+                detail: this.translate.translate('Could not export services: ') + errorMessage(error)
             });
         }
     }
@@ -490,7 +1241,7 @@ export class MainComponent implements OnInit {
                         this.messageService.add({
                             severity: 'error',
                             summary: this.translate.translate('Import Error'),
-                            detail: this.translate.translate('Could not import services: ') + error
+                            detail: this.translate.translate('Could not import services: ') + errorMessage(error)
                         });
                     }
                 });
@@ -499,7 +1250,7 @@ export class MainComponent implements OnInit {
             this.messageService.add({
                 severity: 'error',
                 summary: this.translate.translate('Import Error'),
-                detail: this.translate.translate('Could not import services: ') + error
+                detail: this.translate.translate('Could not import services: ') + errorMessage(error)
             });
         }
     }
@@ -508,9 +1259,29 @@ export class MainComponent implements OnInit {
         this.showPasswordChangeDialog.set(true);
     }
 
-    async confirmPasswordChange(newPassword: string) {
+    async chooseKeyFile() {
+        const path = await open({ multiple: false, directory: false });
+        this.keyFile.set(path ?? undefined);
+    }
+
+    async createKeyFile() {
+        const path = await save({ defaultPath: 'rauthy.key' });
+        if (!path) {
+            return;
+        }
+        this.totpService.generateKeyFile(path).subscribe({
+            next: () => this.keyFile.set(path),
+            error: error => this.messageService.add({
+                severity: 'error',
+                summary: this.translate.translate('Could not create the key file'),
+                detail: errorMessage(error)
+            })
+        });
+    }
+
+    async confirmPasswordChange(password: string, newPassword: string) {
         try {
-            const subscription = this.totpService.changePassword(newPassword).subscribe({
+            const subscription = this.totpService.changePassword(password, newPassword, this.keyFile()).subscribe({
                 next: () => {
                     subscription.unsubscribe();
                     this.showPasswordChangeDialog.set(false);
@@ -525,7 +1296,7 @@ export class MainComponent implements OnInit {
                     this.messageService.add({
                         severity: 'error',
                         summary: this.translate.translate('Password Change Error'),
-                        detail: this.translate.translate('Could not change password: ') + error
+                        detail: this.translate.translate('Could not change password: ') + errorMessage(error)
                     });
                 }
             });
@@ -533,7 +1304,7 @@ export class MainComponent implements OnInit {
             this.messageService.add({
                 severity: 'error',
                 summary: this.translate.translate('Password Change Error'),
-                detail: this.translate.translate('Could not change password: ') + error
+                detail: this.translate.translate('Could not change password: ') + errorMessage(error)
             });
         }
     }
@@ -558,9 +1329,28 @@ export class MainComponent implements OnInit {
         });
     }
 
-    private performLogout() {
+    /**
+     * Postpones the backend idle lock while the user is interacting with the app.
+     * Reports are throttled, as the idle timeout is measured in minutes.
+     */
+    private reportUserActivity() {
+        let lastReport = 0;
+        const report = () => {
+            const now = Date.now();
+            if (now - lastReport > 30000) {
+                lastReport = now;
+                invoke('report_activity');
+            }
+        };
+        ['pointerdown', 'pointermove', 'keydown', 'wheel'].forEach(eventName => {
+            window.addEventListener(eventName, report, { passive: true });
+        });
+    }
+
+    private performLogout(clearStoredPassword: boolean = true) {
         // Clear all data and reset to initial state
         this.totpItems.set(new Map<string, Service>());
+        this.countdownSubscription?.unsubscribe();
         this.tokensMap.clear();
         this.tokensDuration.clear();
         this.showDialog.set(false);
@@ -570,10 +1360,13 @@ export class MainComponent implements OnInit {
         this.askForPasswordStorage.set(false);
         this.selectedService = undefined;
         this.serviceToDelete = undefined;
-        this.encryptedPassword = "";
         
-        // Clear stored encrypted password
-        this.localStorage.removeItem('encryptedPassword');
+        // Clear stored encrypted password. When the vault is locked automatically we keep it,
+        // so the user can unlock it again with biometrics
+        if (clearStoredPassword) {
+            this.encryptedPassword = "";
+            this.localStorage.removeItem('encryptedPassword');
+        }
         
         // Emit authentication state as false
         this.emitAuthenticationState(false);
@@ -11,7 +11,7 @@ import { ProgressSpinnerModule } from 'primeng/progressspinner';
 
 import { ImageModule } from 'primeng/image';
 
-import { Service } from '../../models/service.model';
+import { Service, ServiceEdit } from '../../models/service.model';
 import { invoke } from '@tauri-apps/api/core';
 
 @Component({
@@ -37,7 +37,9 @@ export class ServiceEditComponent {
     visible = model(false);
     @Input() service?: Service;
     @Output() visibleChange = new EventEmitter<boolean>();
-    @Output() serviceEdited = new EventEmitter<{id: string, name: string, issuer: string}>();
+    @Output() serviceEdited = new EventEmitter<ServiceEdit>();
+    // The user wants to see the previous versions of the service
+    @Output() historyRequested = new EventEmitter<Service>();
 
     loading = signal(false);
 
@@ -45,6 +47,10 @@ export class ServiceEditComponent {
         name: ['', Validators.required],
         issuer: ['', Validators.required],
         icon: ['', Validators.required],
+        // Separated by commas
+        tags: [''],
+        folder: [''],
+        favorite: [false],
     });
 
     ngOnChanges() {
@@ -53,21 +59,36 @@ export class ServiceEditComponent {
                 name: this.service.name,
                 issuer: this.service.issuer,
                 icon: this.service.icon,
+                tags: (this.service.tags ?? []).join(', '),
+                folder: this.service.folder ?? '',
+                favorite: this.service.favorite ?? false,
             });
         }
     }
 
     onSubmit() {
         if (this.editForm.valid && this.service) {
+            const value = this.editForm.value;
             this.serviceEdited.emit({
                 id: this.service.id,
-                ...this.editForm.value
-            } as {id: string, name: string, issuer: string});
+                name: value.name ?? '',
+                issuer: value.issuer ?? '',
+                tags: (value.tags ?? '').split(',').map(tag => tag.trim()).filter(tag => tag !== ''),
+                folder: value.folder?.trim() || null,
+                favorite: value.favorite ?? false,
+            });
             this.visible.set(false);
             this.visibleChange.emit(false);
         }
     }
 
+    showHistory() {
+        if (this.service) {
+            this.historyRequested.emit(this.service);
+            this.onHide();
+        }
+    }
+
     onHide() {
         this.visible.update(old => false);
         this.visibleChange.emit(false);
@@ -11,7 +11,8 @@ import { MenuModule } from 'primeng/menu';
 import { FormsModule } from '@angular/forms';
 
 import { TotpToken } from '../../models/token.model';
-import { Service } from '../../models/service.model';
+import { CopiedToken, Service } from '../../models/service.model';
+import { Highlight, SearchField } from '../../models/search.model';
 import { MenuItem } from 'primeng/api';
 import { isMobile } from '../../utils/platform';
 
@@ -36,7 +37,9 @@ export class ServiceItemComponent {
     @Input() service!: Service;
     @Input() tokensMap = new Map<string, TotpToken>();
     @Input() tokensDuration = new Map<string, number>();
-    @Output() copyToken = new EventEmitter<string>();
+    // The characters that matched the search
+    @Input() highlights: Highlight[] = [];
+    @Output() copyToken = new EventEmitter<CopiedToken>();
     @Output() editService = new EventEmitter<Service>();
     @Output() deleteService = new EventEmitter<Service>();
     @Output() itemChange = new EventEmitter<void>();
@@ -68,6 +71,26 @@ export class ServiceItemComponent {
     ];
     }
 
+    // Splits the text of a field in the parts that matched the search and the ones that didn't
+    segments(field: SearchField, text: string): { text: string, matched: boolean }[] {
+        const ranges = this.highlights.find(highlight => highlight.field === field && highlight.value === text)?.ranges ?? [];
+        // Ranges count characters, not UTF-16 units
+        const chars = Array.from(text);
+        const segments = [];
+        let next = 0;
+        for (const [start, end] of ranges) {
+            if (start > next) {
+                segments.push({ text: chars.slice(next, start).join(''), matched: false });
+            }
+            segments.push({ text: chars.slice(start, end).join(''), matched: true });
+            next = end;
+        }
+        if (next < chars.length) {
+            segments.push({ text: chars.slice(next).join(''), matched: false });
+        }
+        return segments;
+    }
+
     onImageError(event: any, service: Service) {
         console.error("Couldn't load service logo at: ", event.srcElement?.currentSrc);
         
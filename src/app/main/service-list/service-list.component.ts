@@ -11,9 +11,11 @@ import { MenuModule } from 'primeng/menu';
 import { TooltipModule } from 'primeng/tooltip';
 import { NgxSwipeMenuComponent, SwipeMenuActions } from 'ngx-swipe-menu';
 import { FormsModule } from '@angular/forms';
+import { CdkDragDrop, DragDropModule, moveItemInArray } from '@angular/cdk/drag-drop';
 
 import { ServiceItemComponent } from '../service-item/service-item.component';
-import { Service } from '../../models/service.model';
+import { CopiedToken, Service, ServiceOrder, SortMode } from '../../models/service.model';
+import { SearchResults } from '../../models/search.model';
 import { TotpToken } from '../../models/token.model';
 import { isMobile } from '../../utils/platform';
 
@@ -34,6 +36,7 @@ import { isMobile } from '../../utils/platform';
     MenuModule,
     TooltipModule,
     NgxSwipeMenuComponent,
+    DragDropModule,
     ServiceItemComponent
 ],
     templateUrl: './service-list.component.html',
@@ -45,14 +48,50 @@ export class ServiceListComponent {
     @Output() totpItemsChange = new EventEmitter<Map<string, Service>>();
     @Input() tokensMap = new Map<string, TotpToken>();
     @Input() tokensDuration = new Map<string, number>();
+    order = input<ServiceOrder>({ sort: 'manual', ids: [] });
+    searchResults = input<SearchResults>({ query: '', matches: [] });
+    // The search typed, for the backend to match it
+    @Output() search = new EventEmitter<string>();
+    @Output() sortChange = new EventEmitter<SortMode>();
+    // The ids of the services shown, in the order they were dragged to
+    @Output() reorder = new EventEmitter<string[]>();
     @Output() addService = new EventEmitter<void>();
-    @Output() copyToken = new EventEmitter<string>();
+    @Output() copyToken = new EventEmitter<CopiedToken>();
     @Output() editService = new EventEmitter<Service>();
     @Output() deleteService = new EventEmitter<Service>();
+    // Tag or move the services shown, by id
+    @Output() organizeServices = new EventEmitter<string[]>();
     
     isMobile = signal(isMobile());
     itemList = computed(() => this.filter(this.totpItems().values()));//Array.from(this.totpItems.values());
     searchFilter = signal('');
+    folderFilter = signal('');
+    tagFilter = signal('');
+    favoritesOnly = signal(false);
+    sortModes: { value: SortMode, label: string }[] = [
+        { value: 'manual', label: 'Manual order' },
+        { value: 'alphabetical', label: 'Alphabetical' },
+        { value: 'mostUsed', label: 'Most used' },
+        { value: 'recentlyAdded', label: 'Recently added' },
+        { value: 'recentlyUsed', label: 'Recently used' },
+    ];
+    // The five codes used the most, to copy them without searching
+    mostUsed = computed(() => Array.from(this.totpItems().values())
+        .filter(service => (service.uses ?? 0) > 0)
+        .sort((a, b) => (b.uses ?? 0) - (a.uses ?? 0))
+        .slice(0, 5));
+    // Only the services used in the last days, 0 for all of them
+    usedWithinDays = signal(0);
+    recencyFilters = [
+        { days: 0, label: 'Used any time' },
+        { days: 7, label: 'Used this week' },
+        { days: 30, label: 'Used this month' },
+    ];
+    // Dragging a filtered list would move the services shown before the hidden ones
+    canReorder = computed(() => this.order().sort === 'manual' && !this.isMobile() &&
+        this.searchFilter() === "" && this.folderFilter() === "" && this.tagFilter() === "" && !this.favoritesOnly() && this.usedWithinDays() === 0);
+    folders = computed(() => this.distinct(Array.from(this.totpItems().values()).map(service => service.folder ?? '')));
+    tags = computed(() => this.distinct(Array.from(this.totpItems().values()).flatMap(service => service.tags ?? [])));
     actionList = [
         {
             name: 'edit',
@@ -80,11 +119,57 @@ export class ServiceListComponent {
         this.deleteService.emit(service);
     }
 
+    toggleFavorites() {
+        this.favoritesOnly.update(favoritesOnly => !favoritesOnly);
+    }
+
+    copyMostUsed(service: Service) {
+        const token = this.tokensMap.get(service.id)?.token;
+        if (token) {
+            this.copyToken.emit({ serviceId: service.id, token });
+        }
+    }
+
+    onDrop(event: CdkDragDrop<Service[]>) {
+        const ids = this.itemList().map(service => service.id);
+        moveItemInArray(ids, event.previousIndex, event.currentIndex);
+        this.reorder.emit(ids);
+    }
+
+    onOrganize() {
+        this.organizeServices.emit(this.itemList().map(service => service.id));
+    }
+
+    onSearch(query: string) {
+        this.searchFilter.set(query);
+        this.search.emit(query);
+    }
+
+    highlightsOf(service: Service) {
+        return this.searchResults().matches.find(found => found.id === service.id)?.highlights ?? [];
+    }
+
+    // Favorites are listed first, then the services in the order sent by the backend.
+    // Services added since the order was loaded go last. Search results are listed best
+    // matches first instead.
     private filter(items: IterableIterator<Service>) {
+        const searching = this.searchFilter().trim() !== "";
+        const ids = searching ? this.searchResults().matches.map(found => found.id) : this.order().ids;
+        const positions = new Map(ids.map((id, index) => [id, index]));
+        const position = (service: Service) => positions.get(service.id) ?? positions.size;
         return Array.from(items)
-            .filter((service) => {
-                return this.searchFilter() === "" ||
-                    (service.issuer.includes(this.searchFilter()) || service.name.includes(this.searchFilter()));
-            });
+            .filter(service => !searching || positions.has(service.id))
+            .filter(service => !this.favoritesOnly() || service.favorite)
+            .filter(service => this.usedWithinDays() === 0 ||
+                (service.lastUsed ?? 0) * 1000 >= Date.now() - this.usedWithinDays() * 24 * 60 * 60 * 1000)
+            .filter(service => this.folderFilter() === "" || service.folder === this.folderFilter())
+            .filter(service => this.tagFilter() === "" || (service.tags ?? []).includes(this.tagFilter()))
+            .sort((a, b) => searching
+                ? position(a) - position(b)
+                : Number(b.favorite ?? false) - Number(a.favorite ?? false) || position(a) - position(b));
+    }
+
+    private distinct(values: string[]) {
+        return Array.from(new Set(values.filter(value => value !== ""))).sort((a, b) => a.localeCompare(b));
     }
 }
@@ -0,0 +1,17 @@
+export interface AuditEvent {
+    // Unix timestamp, in seconds
+    timestamp: number;
+    action: 'unlock' | 'add' | 'update' | 'delete' | 'import' | 'export' | 'passwordChange' | 'revealSecret';
+    target: string | null;
+    device: string;
+    // The member that opened a team vault
+    member: string | null;
+    // Code of the error, when the operation failed
+    error: string | null;
+}
+
+export interface AuditTrail {
+    events: AuditEvent[];
+    // False when lines of the log were edited or removed
+    intact: boolean;
+}
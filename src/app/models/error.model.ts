@@ -0,0 +1,15 @@
+/**
+ * Error returned by every backend command. `code` is stable and can be used
+ * as a translation key, `message` is an english fallback.
+ */
+export interface RauthyError {
+    code: string;
+    message: string;
+}
+
+export function errorMessage(error: unknown): string {
+    if (error && typeof error === 'object' && 'message' in error) {
+        return (error as RauthyError).message;
+    }
+    return String(error);
+}
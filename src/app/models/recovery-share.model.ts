@@ -0,0 +1,5 @@
+export interface RecoveryShare {
+    text: string;
+    // SVG image of the QR code with the same text
+    qrCode: string;
+}
@@ -0,0 +1,21 @@
+export type SearchField = 'issuer' | 'name' | 'tag' | 'folder';
+
+export interface Highlight {
+    field: SearchField;
+    // The text of the field, to tell the tags apart
+    value: string;
+    // Ranges of matched characters, as [start, end)
+    ranges: [number, number][];
+}
+
+export interface SearchMatch {
+    id: string;
+    score: number;
+    highlights: Highlight[];
+}
+
+// The matches of the last search, best first
+export interface SearchResults {
+    query: string;
+    matches: SearchMatch[];
+}
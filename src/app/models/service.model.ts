@@ -7,10 +7,63 @@ export enum TotpAlgorithm {
 export interface Service {
     id: string;
     issuer: string;
-    secret: string;
     name: string;
     algorithm: TotpAlgorithm; // or use enum if you prefer
     digits: number;
     period: number;
     icon?: string;
-} 
+    tags: string[];
+    folder?: string | null;
+    favorite: boolean;
+    // How many times its token was copied or its secret revealed
+    uses?: number;
+    // Unix timestamp, in seconds, 0 if it was never used
+    lastUsed?: number;
+}
+
+// What the edit dialog changes
+export interface ServiceEdit {
+    id: string;
+    name: string;
+    issuer: string;
+    tags: string[];
+    folder: string | null;
+    favorite: boolean;
+}
+
+export type SortMode = 'manual' | 'alphabetical' | 'mostUsed' | 'recentlyAdded' | 'recentlyUsed';
+
+// A service that wasn't used for months
+export interface StaleService {
+    id: string;
+    label: string;
+    // Unix timestamps, in seconds. lastUsed is 0 if it was never used
+    lastUsed: number;
+    uses: number;
+    created: number;
+}
+
+// The ids of the services in the order they're listed
+export interface ServiceOrder {
+    sort: SortMode;
+    ids: string[];
+}
+
+// A token copied from the list
+export interface CopiedToken {
+    serviceId: string;
+    token: string;
+}
+
+// The tags and folders used in the vault, sorted
+export interface ServiceGroups {
+    tags: string[];
+    folders: string[];
+}
+
+// A service as it was before it was replaced or deleted, without its secret
+export interface ServiceVersion {
+    service: Service;
+    // Unix timestamp, in seconds
+    replaced: number;
+}
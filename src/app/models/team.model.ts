@@ -0,0 +1,11 @@
+// The key pair that identifies the user in team vaults. The secret key never leaves the backend
+export interface Identity {
+    name: string;
+    // Given to the team, so they can add this device to their vaults
+    publicKey: string;
+}
+
+export interface TeamMember {
+    name: string;
+    publicKey: string;
+}
@@ -1,5 +1,9 @@
+import { RauthyError } from './error.model';
+
 
 export interface TotpToken {
     token: string,
-    nextStepTime: Date
+    nextStepTime: Date,
+    // Set when the backend couldn't generate the token (e.g. a malformed secret)
+    error?: RauthyError
 }
@@ -0,0 +1,6 @@
+export interface TransferOffer {
+    // Text of the QR code, for devices without a camera
+    uri: string;
+    // SVG image of the QR code
+    qrCode: string;
+}
@@ -0,0 +1,18 @@
+export interface VaultEntry {
+    id: string;
+    name: string;
+    // Relative to the data dir, or absolute for vaults kept elsewhere
+    path: string;
+}
+
+export interface VaultRegistry {
+    vaults: VaultEntry[];
+    // Id of the vault opened by the login screen
+    active: string;
+}
+
+// The WebDAV server the open vault is synced to. The password never leaves the backend
+export interface WebDavStatus {
+    url: string;
+    username: string;
+}
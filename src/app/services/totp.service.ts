@@ -1,10 +1,20 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { Injectable } from '@angular/core';
+import { RecoveryShare } from '../models/recovery-share.model';
+import { VaultRegistry, WebDavStatus } from '../models/vault.model';
+import { TransferOffer } from '../models/transfer.model';
+import { Identity, TeamMember } from '../models/team.model';
+import { AuditTrail } from '../models/audit.model';
 import { from } from 'rxjs';
 
-import { Service } from '../models/service.model';
+import { Service, ServiceGroups, ServiceOrder, ServiceVersion, SortMode, StaleService } from '../models/service.model';
+import { SearchMatch } from '../models/search.model';
 import { Observable, Subject } from 'rxjs';
 import { TotpToken } from '../models/token.model';
+import { RauthyError } from '../models/error.model';
+
+type TokenState = {token: string, next_step_time: number} | {error: RauthyError};
 
 @Injectable({
     providedIn: 'root',
@@ -16,12 +26,32 @@ export class TotpService {
     tokensContent: Map<string, TotpToken> = new Map<string, TotpToken>();
     tokens: Subject<typeof this.tokensContent> = new Subject<typeof this.tokensContent>();
 
+    constructor() {
+        // The backend pushes only the tokens that changed, whenever a service crosses its next step
+        listen<Record<string, TokenState>>('tokens-updated', event => {
+            Object.entries(event.payload).forEach((token) => {
+                this.tokensContent.set(token[0], this.toTotpToken(token[1]));
+            });
+            this.tokens.next(this.tokensContent);
+        });
+    }
+
+    private toTotpToken(state: TokenState): TotpToken {
+        if ('error' in state) {
+            return { token: '', nextStepTime: new Date(0), error: state.error } as TotpToken;
+        }
+        return {
+            token: state.token,
+            nextStepTime: new Date(state.next_step_time * 1000)
+        } as TotpToken;
+    }
+
     private setupServices(services: object) {
         this.servicesContent = new Map(Object.entries(services));
     }
 
-    setupStorageKeys(password: string): Observable<Map<string, Service>> {
-        invoke<object>('setup_storage_keys', { userPass: password }).then(services => {
+    setupStorageKeys(password: string, keyFile?: string): Observable<Map<string, Service>> {
+        invoke<object>('setup_storage_keys', { userPass: password, keyFile }).then(services => {
             this.setupServices(services);
             this.services.next(this.servicesContent);
         }).catch(error => {
@@ -32,6 +62,41 @@ export class TotpService {
         return this.services.asObservable();
     }
 
+    unlockWithRecoveryKey(recoveryKey: string, newPassword: string, keyFile?: string): Observable<Map<string, Service>> {
+        return from(invoke<object>('unlock_with_recovery_key', { recoveryKey, newPassword, keyFile }).then(services => {
+            this.setupServices(services);
+            return this.servicesContent;
+        }));
+    }
+
+    unlockWithRecoveryShares(shares: string[], newPassword: string, keyFile?: string): Observable<Map<string, Service>> {
+        return from(invoke<object>('unlock_with_recovery_shares', { shares, newPassword, keyFile }).then(services => {
+            this.setupServices(services);
+            return this.servicesContent;
+        }));
+    }
+
+    splitRecoveryKey(password: string, count: number, threshold: number): Observable<RecoveryShare[]> {
+        return from(invoke<RecoveryShare[]>('split_recovery_key', { password, count, threshold }));
+    }
+
+    setDuressPassword(password: string, duressPassword: string): Observable<void> {
+        return from(invoke<void>('set_duress_password', { password, duressPassword }));
+    }
+
+    removeDuressPassword(password: string): Observable<void> {
+        return from(invoke<void>('remove_duress_password', { password }));
+    }
+
+    // The recovery key of a new (or migrated) vault. The backend only returns it once
+    takeRecoveryKey(): Observable<string | null> {
+        return from(invoke<string | null>('take_recovery_key'));
+    }
+
+    regenerateRecoveryKey(password: string): Observable<string> {
+        return from(invoke<string>('regenerate_recovery_key', { password }));
+    }
+
     fetchServicesWithoutPassword(encryptedData: string, options: {[key:string]: any}) {
         const {reason, ...otherOptions} = {...options};
         otherOptions["dataToDecrypt"] = encryptedData;
@@ -78,14 +143,10 @@ export class TotpService {
     }
 
     getServicesTokens(): Observable<Map<string, TotpToken>> {
-        invoke<Record<string, {token: string, next_step_time: number}>>('get_services_tokens').then(tokens => {
+        invoke<Record<string, TokenState>>('get_services_tokens').then(tokens => {
             this.tokensContent = new Map<string, TotpToken>();
             Object.entries(tokens).forEach((token) => {
-                const tokenData = {
-                    token: token[1].token,
-                    nextStepTime: new Date(token[1].next_step_time * 1000)
-                } as TotpToken;
-                this.tokensContent.set(token[0], tokenData);
+                this.tokensContent.set(token[0], this.toTotpToken(token[1]));
             })
             
             this.tokens.next(this.tokensContent);
@@ -93,16 +154,91 @@ export class TotpService {
         return this.tokens;
     }
 
-    updateService(service: Service): Observable<void> {
-        return from(invoke<void>('update_service', { service }));
+    updateService(service: Service, secret?: string): Observable<void> {
+        return from(invoke<void>('update_service', { service, secret }));
+    }
+
+    revealSecret(serviceId: string, password?: string): Observable<string> {
+        return from(invoke<string>('reveal_secret', { serviceId, password }));
     }
 
     deleteService(serviceId: number | string): Observable<void> {
         return from(invoke<void>('delete_service', { serviceId }));
     }
 
-    exportServicesCsv(): Observable<string> {
-        return from(invoke<string>('export_services_csv'));
+    // Versions replaced by edits or imports, oldest first
+    getServiceHistory(serviceId: string): Observable<ServiceVersion[]> {
+        return from(invoke<ServiceVersion[]>('get_service_history', { serviceId }));
+    }
+
+    restoreServiceVersion(serviceId: string, index: number): Observable<Map<string, Service>> {
+        return from(invoke<object>('restore_service_version', { serviceId, index }).then(services => {
+            this.setupServices(services);
+            return this.servicesContent;
+        }));
+    }
+
+    listDeletedServices(): Observable<ServiceVersion[]> {
+        return from(invoke<ServiceVersion[]>('list_deleted_services'));
+    }
+
+    undoDeleteService(serviceId: string): Observable<Map<string, Service>> {
+        return from(invoke<object>('undo_delete_service', { serviceId }).then(services => {
+            this.setupServices(services);
+            return this.servicesContent;
+        }));
+    }
+
+    listServiceGroups(): Observable<ServiceGroups> {
+        return from(invoke<ServiceGroups>('list_service_groups'));
+    }
+
+    tagServices(serviceIds: string[], addTags: string[], removeTags: string[] = []): Observable<Map<string, Service>> {
+        return from(invoke<object>('tag_services', { serviceIds, addTags, removeTags }).then(services => {
+            this.setupServices(services);
+            return this.servicesContent;
+        }));
+    }
+
+    moveServicesToFolder(serviceIds: string[], folder: string | null): Observable<Map<string, Service>> {
+        return from(invoke<object>('move_services_to_folder', { serviceIds, folder }).then(services => {
+            this.setupServices(services);
+            return this.servicesContent;
+        }));
+    }
+
+    // Fuzzy search over the issuers, accounts, tags and folders, best matches first
+    searchServices(query: string, limit?: number): Observable<SearchMatch[]> {
+        return from(invoke<SearchMatch[]>('search_services', { query, limit }));
+    }
+
+    // With the sort mode saved in the settings when none is given
+    listServiceOrder(sort?: SortMode): Observable<ServiceOrder> {
+        return from(invoke<ServiceOrder>('list_service_order', { sort }));
+    }
+
+    setSortMode(sort: SortMode): Observable<ServiceOrder> {
+        return from(invoke<ServiceOrder>('set_sort_mode', { sort }));
+    }
+
+    moveService(serviceId: string, position: number): Observable<ServiceOrder> {
+        return from(invoke<ServiceOrder>('move_service', { serviceId, position }));
+    }
+
+    reorderServices(serviceIds: string[]): Observable<ServiceOrder> {
+        return from(invoke<ServiceOrder>('reorder_services', { serviceIds }));
+    }
+
+    listStaleServices(months: number): Observable<StaleService[]> {
+        return from(invoke<StaleService[]>('list_stale_services', { months }));
+    }
+
+    recordServiceUse(serviceId: string): Observable<void> {
+        return from(invoke<void>('record_service_use', { serviceId }));
+    }
+
+    exportServicesCsv(password: string): Observable<string> {
+        return from(invoke<string>('export_services_csv', { password }));
     }
 
     importServicesCsv(csvContent: string): Observable<Map<string, Service>> {
@@ -113,11 +249,128 @@ export class TotpService {
         }));
     }
 
-    changePassword(newPassword: string): Observable<void> {
-        return from(invoke<void>('change_password', { newPassword }));
+    changePassword(password: string, newPassword: string, keyFile?: string): Observable<void> {
+        return from(invoke<void>('change_password', { password, newPassword, keyFile }));
+    }
+
+    generateKeyFile(path: string): Observable<void> {
+        return from(invoke<void>('generate_key_file', { path }));
     }
 
     closeServicesFile(): Observable<void> {
         return from(invoke<void>('close_services_file'));
     }
+
+    // Merges the changes made to the vault on other devices. The merged services are sent with the 'vault-synced' event
+    syncVault(): Observable<void> {
+        return from(invoke<void>('sync_vault'));
+    }
+
+    // Syncs the open vault with a file on a WebDAV server (e.g. Nextcloud). Fails, keeping the previous one, if the first sync fails.
+    // `password` is the one of the server, `vaultPassword` the one of the open vault
+    setWebDavSync(url: string, username: string, password: string, vaultPassword: string): Observable<void> {
+        return from(invoke<void>('set_webdav_sync', { url, username, password, vaultPassword }));
+    }
+
+    disableWebDavSync(vaultPassword: string): Observable<void> {
+        return from(invoke<void>('disable_webdav_sync', { vaultPassword }));
+    }
+
+    getWebDavSync(): Observable<WebDavStatus | null> {
+        return from(invoke<WebDavStatus | null>('get_webdav_sync'));
+    }
+
+    // Downloads a vault synced by another device as a new vault, unlocked with its own password
+    downloadWebDavVault(name: string, url: string, username: string, password: string): Observable<VaultRegistry> {
+        return from(invoke<VaultRegistry>('download_webdav_vault', { name, url, username, password }));
+    }
+
+    // Starts sending the services to another device, which scans the QR code. Followed by the 'transfer-paired' and 'transfer-finished' events
+    startTransfer(serviceIds: string[], password: string): Observable<TransferOffer> {
+        return from(invoke<TransferOffer>('start_transfer', { serviceIds, password }));
+    }
+
+    // Sends the services once the user checked both devices show the same code, or cancels the transfer.
+    // Sending needs the password
+    confirmTransfer(confirmed: boolean, password?: string): Observable<void> {
+        return from(invoke<void>('confirm_transfer', { confirmed, password }));
+    }
+
+    receiveTransfer(uri: string): Observable<void> {
+        return from(invoke<void>('receive_transfer', { uri }));
+    }
+
+    createIdentity(name: string, password: string): Observable<Identity> {
+        return from(invoke<Identity>('create_identity', { name, password }));
+    }
+
+    getIdentity(): Observable<Identity | null> {
+        return from(invoke<Identity | null>('get_identity'));
+    }
+
+    // Team vaults are opened with the identity of each member, so `password` is the one of the identity
+    createTeamVault(name: string, password: string, location?: string): Observable<Map<string, Service>> {
+        return from(invoke<object>('create_team_vault', { name, password, location }).then(services => {
+            this.setupServices(services);
+            return this.servicesContent;
+        }));
+    }
+
+    listMembers(): Observable<TeamMember[]> {
+        return from(invoke<TeamMember[]>('list_members'));
+    }
+
+    // The new member can read every secret, so the password is checked first
+    addMember(name: string, publicKey: string, password: string): Observable<TeamMember[]> {
+        return from(invoke<TeamMember[]>('add_member', { name, publicKey, password }));
+    }
+
+    // Re-keys the vault, so it's followed by a new recovery key
+    removeMember(publicKey: string, password: string): Observable<TeamMember[]> {
+        return from(invoke<TeamMember[]>('remove_member', { publicKey, password }));
+    }
+
+    getAuditLog(): Observable<AuditTrail> {
+        return from(invoke<AuditTrail>('get_audit_log'));
+    }
+
+    // The log as JSON. The export is recorded in the log too
+    exportAuditLog(): Observable<string> {
+        return from(invoke<string>('export_audit_log'));
+    }
+
+    listVaults(): Observable<VaultRegistry> {
+        return from(invoke<VaultRegistry>('list_vaults'));
+    }
+
+    createVault(name: string, password: string, keyFile?: string, location?: string): Observable<Map<string, Service>> {
+        return from(invoke<object>('create_vault', { name, password, keyFile, location }).then(services => {
+            this.setupServices(services);
+            return this.servicesContent;
+        }));
+    }
+
+    openVault(id: string, password: string, keyFile?: string): Observable<Map<string, Service>> {
+        return from(invoke<object>('open_vault', { id, password, keyFile }).then(services => {
+            this.setupServices(services);
+            return this.servicesContent;
+        }));
+    }
+
+    // Only vaults can be added. `password` is only needed for files of the first version, which have no header
+    addVaultFile(name: string, path: string, password: string): Observable<VaultRegistry> {
+        return from(invoke<VaultRegistry>('add_vault_file', { name, path, password }));
+    }
+
+    moveVault(id: string, directory: string): Observable<VaultRegistry> {
+        return from(invoke<VaultRegistry>('move_vault', { id, directory }));
+    }
+
+    renameVault(id: string, name: string): Observable<void> {
+        return from(invoke<void>('rename_vault', { id, name }));
+    }
+
+    deleteVault(id: string, password: string, keyFile?: string): Observable<void> {
+        return from(invoke<void>('delete_vault', { id, password, keyFile }));
+    }
 } 